@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use bimap::BiMap;
+
+use crate::error::BBScriptError;
+use crate::game_config::BBSNumber;
+
+/// Starts a variable declaration, anything else the grammar accepts as a declaration is a `#const`
+pub const VARIABLE_DIRECTIVE: &str = "#var";
+
+/// Names declared by readable scripts with `#var ComboCounter = 51` and `#const MAX_HITS = 5`,
+/// so mods don't need their own config to name them.
+/// Variables are used like the config's `named_variables`, constants in `Val(...)` and in place of numbers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptAliases {
+    pub variables: BiMap<BBSNumber, String>,
+    pub constants: BTreeMap<String, BBSNumber>,
+}
+
+impl ScriptAliases {
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty() && self.constants.is_empty()
+    }
+
+    /// Declares a variable name for `Mem(id)`. Declaring it again is only allowed with the same ID
+    pub fn declare_variable(&mut self, name: String, id: BBSNumber) -> Result<(), BBScriptError> {
+        if self.constants.contains_key(&name) {
+            return Err(BBScriptError::InvalidAlias(format!(
+                "`{name}` is already declared as a constant"
+            )));
+        }
+
+        match (
+            self.variables.get_by_right(&name),
+            self.variables.get_by_left(&id),
+        ) {
+            (Some(declared), _) if *declared != id => Err(BBScriptError::InvalidAlias(format!(
+                "variable `{name}` is already declared as {declared}"
+            ))),
+            (_, Some(declared)) if *declared != name => Err(BBScriptError::InvalidAlias(format!(
+                "variable {id} is already declared as `{declared}`"
+            ))),
+            _ => {
+                self.variables.insert(id, name);
+                Ok(())
+            }
+        }
+    }
+
+    /// Declares a constant. Declaring it again is only allowed with the same value
+    pub fn declare_constant(
+        &mut self,
+        name: String,
+        value: BBSNumber,
+    ) -> Result<(), BBScriptError> {
+        if self.variables.contains_right(&name) {
+            return Err(BBScriptError::InvalidAlias(format!(
+                "`{name}` is already declared as a variable"
+            )));
+        }
+
+        match self.constants.get(&name) {
+            Some(declared) if *declared != value => Err(BBScriptError::InvalidAlias(format!(
+                "constant `{name}` is already declared as {declared}"
+            ))),
+            _ => {
+                self.constants.insert(name, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Declares everything declared in `other`
+    pub fn extend(&mut self, other: ScriptAliases) -> Result<(), BBScriptError> {
+        for (id, name) in other.variables {
+            self.declare_variable(name, id)?;
+        }
+        for (name, value) in other.constants {
+            self.declare_constant(name, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rebuilder::rebuild_readable;
+    use crate::{BBScriptError, ScriptAliases, SupportedGame};
+    use byteorder::LittleEndian;
+
+    const DECLARED: &str = r"// names shared with other scripts
+#var ComboCounter = 9999
+#const MAX_HITS = 5
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', MAX_HITS
+  ComboCounter += MAX_HITS
+  if (Mem(ComboCounter) > Val(MAX_HITS)) {
+    exitState:
+  }
+endState:
+";
+
+    const PLAIN: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  modifyVar: (ADD), Mem(9999), Val(5)
+  ifOperation: (IS_GREATER), Mem(9999), Val(5)
+    exitState:
+  endIf:
+endState:
+";
+
+    #[test]
+    fn script_aliases() {
+        let config = SupportedGame::Ggst.into_config();
+        let bytes = rebuild_readable::<LittleEndian>(&config, DECLARED).unwrap();
+        assert_eq!(
+            bytes,
+            rebuild_readable::<LittleEndian>(&config, PLAIN).unwrap()
+        );
+
+        // the parser names variables with the same declarations, without changing the config's hash
+        let mut with_aliases = SupportedGame::Ggst.into_config();
+        with_aliases.aliases = ScriptAliases::read(DECLARED).unwrap();
+        assert_eq!(with_aliases.aliases.constants["MAX_HITS"], 5);
+        assert_eq!(with_aliases.content_hash(), config.content_hash());
+
+        let readable = with_aliases
+            .parse_to_string::<LittleEndian>(&bytes, 12)
+            .unwrap();
+        assert!(readable.contains("ComboCounter += 5"), "{readable}");
+        assert!(readable.contains("if (ComboCounter > 5)"), "{readable}");
+        assert_eq!(
+            rebuild_readable::<LittleEndian>(&with_aliases, &readable).unwrap(),
+            bytes
+        );
+
+        assert!(matches!(
+            rebuild_readable::<LittleEndian>(&config, "#const A = 1\n#const A = 2\nendState:\n"),
+            Err(BBScriptError::InvalidAlias(_))
+        ));
+        assert!(matches!(
+            rebuild_readable::<LittleEndian>(&config, "#var A = 1\n#var B = 1\nendState:\n"),
+            Err(BBScriptError::InvalidAlias(_))
+        ));
+        assert!(matches!(
+            rebuild_readable::<LittleEndian>(&config, "sprite: s32'nmc000_00', MAX\n"),
+            Err(BBScriptError::NoConstantName(name)) if name == "MAX"
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+use byteorder::ByteOrder;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BBScriptError;
+use crate::game_config::{CodeBlock, ScriptConfig};
+use crate::parser::{
+    ArgValue, InstructionIdentifier, InstructionValue, LocatedInstruction, ParseRecovery,
+};
+use crate::rebuilder::{rebuild_instructions, rebuild_readable};
+
+/// Index of a [`Node`] within a [`Script`]
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeKind {
+    /// A top level block opened by the first instruction in the config's `jump_table_ids`
+    State,
+    /// Any other top level block, such as subroutines
+    Subroutine,
+    /// A block nested inside of a state or subroutine
+    Block,
+    /// An instruction that doesn't open a block
+    Instruction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub parent: Option<NodeId>,
+    /// The instruction opening the block, or the instruction itself for [`NodeKind::Instruction`]
+    pub begin: LocatedInstruction,
+    pub children: Vec<NodeId>,
+    /// The instruction closing the block.
+    /// This is `None` for instructions, blocks opened by [`CodeBlock::BeginNonrecursive`],
+    /// and blocks that were never closed in the script
+    pub end: Option<LocatedInstruction>,
+}
+
+impl Node {
+    /// Name given to the block by its first string argument, such as a state name
+    pub fn name(&self) -> Option<&str> {
+        self.begin
+            .instruction
+            .args
+            .iter()
+            .find_map(|arg| match arg {
+                ArgValue::String32(s) => Some(s.0.as_str()),
+                ArgValue::String16(s) => Some(s.0.as_str()),
+                _ => None,
+            })
+    }
+
+    pub fn is_block(&self) -> bool {
+        self.kind != NodeKind::Instruction
+    }
+}
+
+/// A script as a tree of states and subroutines holding nested blocks.
+/// Nodes are stored in source order, so iterating over `nodes` visits every instruction that opens a node in the order it appears in the script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Script {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    /// The [`InstructionIdentifier::JumpTable`] instruction of a parsed script whose table the rebuilder wouldn't generate
+    #[serde(default)]
+    jump_table: Option<InstructionValue>,
+}
+
+impl Script {
+    /// Builds the tree from a parsed script using the block types in the config
+    pub fn new(
+        config: &ScriptConfig,
+        program: Vec<LocatedInstruction>,
+    ) -> Result<Self, BBScriptError> {
+        let state_id = config.jump_table_ids.first().copied();
+
+        let mut script = Self {
+            nodes: Vec::with_capacity(program.len()),
+            roots: Vec::new(),
+            jump_table: None,
+        };
+
+        // open blocks, along with whether they were opened by a `BeginNonrecursive` instruction
+        let mut open: Vec<(NodeId, bool)> = Vec::new();
+
+        for located in program {
+            // raw data has no config entry, but never changes the block structure
+            if located.instruction.identifier == InstructionIdentifier::RawData {
+                let parent = open.last().map(|(id, _)| *id);
+                script.push(NodeKind::Instruction, parent, located);
+
+                continue;
+            }
+
+            let info = config.get_by_identifier(&located.instruction.identifier)?;
+            let block_type = info.block_type();
+
+            // nonrecursive blocks end when any block containing them ends
+            if matches!(block_type, CodeBlock::End | CodeBlock::EndState) {
+                while let Some((_, true)) = open.last() {
+                    open.pop();
+                }
+            }
+
+            match block_type {
+                CodeBlock::Begin => {
+                    let kind = if !open.is_empty() {
+                        NodeKind::Block
+                    } else if Some(info.id()) == state_id {
+                        NodeKind::State
+                    } else {
+                        NodeKind::Subroutine
+                    };
+
+                    let parent = open.last().map(|(id, _)| *id);
+                    open.push((script.push(kind, parent, located), false));
+                }
+                CodeBlock::BeginNonrecursive => {
+                    // another nonrecursive block of the same type ends the previous one
+                    if let Some((id, true)) = open.last() {
+                        if script.nodes[*id].begin.instruction.identifier
+                            == located.instruction.identifier
+                        {
+                            open.pop();
+                        }
+                    }
+
+                    let parent = open.last().map(|(id, _)| *id);
+                    open.push((script.push(NodeKind::Block, parent, located), true));
+                }
+                CodeBlock::End if !open.is_empty() => {
+                    let (id, _) = open.pop().unwrap();
+                    script.nodes[id].end = Some(located);
+                }
+                CodeBlock::EndState if !open.is_empty() => {
+                    // ends the outermost block, leaving any unclosed inner blocks without an end
+                    let (id, _) = open[0];
+                    open.clear();
+                    script.nodes[id].end = Some(located);
+                }
+                // unbalanced ends are kept as plain instructions so the script still rebuilds
+                _ => {
+                    let parent = open.last().map(|(id, _)| *id);
+                    script.push(NodeKind::Instruction, parent, located);
+                }
+            }
+        }
+
+        Ok(script)
+    }
+
+    fn push(
+        &mut self,
+        kind: NodeKind,
+        parent: Option<NodeId>,
+        begin: LocatedInstruction,
+    ) -> NodeId {
+        let id = self.nodes.len();
+
+        self.nodes.push(Node {
+            kind,
+            parent,
+            begin,
+            children: Vec::new(),
+            end: None,
+        });
+
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Top level nodes in the order they appear in the script
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.roots_of_kind(NodeKind::State)
+    }
+
+    pub fn subroutines(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.roots_of_kind(NodeKind::Subroutine)
+    }
+
+    fn roots_of_kind(&self, kind: NodeKind) -> impl Iterator<Item = NodeId> + '_ {
+        self.roots
+            .iter()
+            .copied()
+            .filter(move |id| self.nodes[*id].kind == kind)
+    }
+
+    /// Finds a state by the name given in its first argument
+    pub fn find_state(&self, name: &str) -> Option<NodeId> {
+        self.states()
+            .find(|id| self.nodes[*id].name() == Some(name))
+    }
+
+    /// Byte range covered by a node and everything inside of it
+    pub fn span(&self, id: NodeId) -> std::ops::Range<usize> {
+        let node = &self.nodes[id];
+        let start = node.begin.offset;
+
+        let mut last = id;
+        loop {
+            let node = &self.nodes[last];
+            if let Some(end) = &node.end {
+                return start..end.offset + end.size;
+            }
+
+            match node.children.last() {
+                Some(child) => last = *child,
+                None => return start..node.begin.offset + node.begin.size,
+            }
+        }
+    }
+
+    /// Every instruction of a node in script order, including the ones closing blocks
+    pub fn node_instructions(&self, id: NodeId) -> Vec<&LocatedInstruction> {
+        let mut out = Vec::new();
+        self.collect_instructions(id, &mut out);
+        out
+    }
+
+    fn collect_instructions<'a>(&'a self, id: NodeId, out: &mut Vec<&'a LocatedInstruction>) {
+        let node = &self.nodes[id];
+        out.push(&node.begin);
+
+        for child in &node.children {
+            self.collect_instructions(*child, out);
+        }
+
+        if let Some(end) = &node.end {
+            out.push(end);
+        }
+    }
+
+    /// Flattens the tree back into the instruction list it was built from
+    pub fn instructions(&self) -> Vec<InstructionValue> {
+        self.roots
+            .iter()
+            .flat_map(|id| self.node_instructions(*id))
+            .map(|i| i.instruction.clone())
+            .collect()
+    }
+
+    /// The jump table recorded when the script was parsed, if it needs one to rebuild the same table
+    pub fn jump_table(&self) -> Option<&InstructionValue> {
+        self.jump_table.as_ref()
+    }
+
+    /// Assembles the script back into its binary form, keeping the recorded jump table
+    pub fn to_bytes<B: ByteOrder>(&self, config: &ScriptConfig) -> Result<Vec<u8>, BBScriptError> {
+        let program = self
+            .jump_table
+            .iter()
+            .cloned()
+            .chain(self.instructions())
+            .collect();
+
+        rebuild_instructions::<B>(config, program)
+    }
+}
+
+impl ScriptConfig {
+    /// Parses a script into a [`Script`] tree
+    pub fn parse_tree<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Script, BBScriptError> {
+        let input = input.as_ref();
+        let (jump_table, base) = self.read_jump_table::<B>(input)?;
+        let program =
+            self.parse_script::<B>(&input[base..], base, ParseRecovery::Strict, &jump_table)?;
+
+        let recorded = self.recorded_jump_table(&jump_table, &program);
+        let mut script = Script::new(self, program)?;
+        script.jump_table = recorded;
+
+        Ok(script)
+    }
+
+    /// Builds a [`Script`] tree from either a binary script or a readable one, which is assembled first.
+    /// Input is treated as readable if it's UTF-8 text without any zero bytes, which binary scripts always have in their jump table
+    pub fn parse_tree_from_any<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Script, BBScriptError> {
+        let input = input.as_ref();
+
+        match std::str::from_utf8(input) {
+            Ok(text) if !input.contains(&0) => {
+                self.parse_tree::<B>(rebuild_readable::<B>(self, text)?)
+            }
+            _ => self.parse_tree::<B>(input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::NodeKind;
+    use crate::fixture::bytes;
+    use crate::rebuilder::rebuild_readable;
+    use crate::SupportedGame;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  upon: (IMMEDIATE)
+    ifOperation: (IS_GREATER), Mem(Tmp), Val(2)
+      exitState:
+    endIf:
+  endUpon:
+endState:
+beginSubroutine: s32'cmnSub'
+  sprite: s32'nmc000_01', 2
+endSubroutine:
+beginState: s32'CmnActCrouch'
+  callSubroutine: s32'cmnSub'
+endState:
+";
+
+    #[test]
+    fn tree_structure() {
+        let config = SupportedGame::Ggst.into_config();
+        let bytes = bytes(SCRIPT);
+
+        let script = config.parse_tree::<LittleEndian>(&bytes).unwrap();
+
+        assert_eq!(script.states().count(), 2);
+        assert_eq!(script.subroutines().count(), 1);
+
+        let stand = script.find_state("CmnActStand").unwrap();
+        let upon = script.node(stand).children[1];
+        let if_op = script.node(upon).children[0];
+        assert_eq!(script.node(upon).kind, NodeKind::Block);
+        assert_eq!(script.parent(if_op), Some(upon));
+        assert_eq!(script.parent(upon), Some(stand));
+
+        // offsets are relative to the end of the jump table
+        assert_eq!(script.span(stand).start, 0);
+        assert_eq!(
+            script.span(stand).end,
+            script.node(script.roots()[1]).begin.offset
+        );
+
+        assert_eq!(script.to_bytes::<LittleEndian>(&config).unwrap(), bytes);
+    }
+
+    #[test]
+    fn tree_keeps_jump_table() {
+        let config = SupportedGame::Ggst.into_config();
+        let mut keep_duplicates = SupportedGame::Ggst.into_config();
+        keep_duplicates.jump_table.deduplicate = false;
+
+        for (script, entries) in [
+            ("beginState: s32'CmnActStand'\nendState:\nbeginState: s32'CmnActCrouch'\nendState:\n", 2),
+            ("beginState: s32'CmnActStand'\nendState:\nbeginState: s32'CmnActCrouch'\nendState:\n\
+              beginState: s32'CmnActStand'\nendState:\n", 3),
+        ] {
+            let mut bytes = rebuild_readable::<LittleEndian>(&keep_duplicates, script).unwrap();
+            assert_eq!(LittleEndian::read_u32(&bytes), entries);
+
+            // swap the first two entries, so the table isn't in the order the rebuilder generates
+            let (first, second) = bytes[4..4 + 2 * 0x24].split_at_mut(0x24);
+            first.swap_with_slice(second);
+
+            let tree = config.parse_tree::<LittleEndian>(&bytes).unwrap();
+            assert!(tree.jump_table().is_some());
+            assert_eq!(tree.to_bytes::<LittleEndian>(&config).unwrap(), bytes);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Endianness;
+
+/// Magic number at the start of UE4 uasset files and the end of uexp files
+pub const UE4_PACKAGE_TAG: u32 = 0x9E2A83C1;
+/// Size of the export header in front of scripts extracted from UE3 UPKs
+pub const UE3_EXPORT_HEADER_SIZE: usize = 0x38;
+
+/// Largest wrapper header searched for a script
+const MAX_PREFIX_SIZE: usize = 0x400;
+/// Jump table entries checked when looking for the start of a script
+const CHECKED_JUMP_ENTRIES: usize = 16;
+const JUMP_ENTRY_SIZE: usize = 0x24;
+const JUMP_ENTRY_NAME_SIZE: usize = 0x20;
+/// Bytes between the size of a UE4 array property tag and the array length:
+/// the array index, inner type name and property GUID flag
+const UE4_TAG_FIELDS_AFTER_SIZE: usize = 4 + 8 + 1;
+
+/// A known format that wraps script data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContainerKind {
+    /// An export extracted from an Unreal Engine 3 UPK
+    Ue3Export,
+    /// The export data of an Unreal Engine 4 asset, stored in a uexp file
+    Ue4Export,
+    /// Data around a script whose location was given manually
+    Sliced,
+}
+
+/// A field in a container header holding the script size plus `extra` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LengthField {
+    /// Offset of the u32 within the prefix, which doesn't have to be aligned
+    pub offset: usize,
+    pub extra: u32,
+}
+
+/// The data around a script that was stripped from a container, kept so the script can be wrapped again after rebuilding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Container {
+    pub kind: ContainerKind,
+    /// Byte order of the length fields
+    pub endianness: Endianness,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub prefix: Vec<u8>,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub suffix: Vec<u8>,
+    /// Fields in `prefix` that have to be updated when the script changes size
+    pub length_fields: Vec<LengthField>,
+}
+
+impl Container {
+    /// Looks for a known container around a script.
+    /// Returns `None` if the input already starts with a script, or no container was recognized
+    pub fn detect(input: &[u8]) -> Option<Self> {
+        let has_tag = input.len() >= 4
+            && LittleEndian::read_u32(&input[input.len() - 4..]) == UE4_PACKAGE_TAG;
+        let (kind, end) = if has_tag {
+            (ContainerKind::Ue4Export, input.len() - 4)
+        } else {
+            (ContainerKind::Ue3Export, input.len())
+        };
+
+        // UE3 exports without a length field still have the script at a fixed offset
+        let script_at = |start: usize| {
+            [Endianness::Little, Endianness::Big].into_iter().find(|e| {
+                input
+                    .get(start..end)
+                    .is_some_and(|s| looks_like_script(s, *e))
+            })
+        };
+
+        if script_at(0).is_some() {
+            return None;
+        }
+
+        // unreal serializes byte arrays with their length right in front of them,
+        // anything after the array is kept in the suffix
+        for start in 4..=MAX_PREFIX_SIZE.min(end) {
+            for endianness in [Endianness::Little, Endianness::Big] {
+                let length = read_u32(endianness, &input[start - 4..start]) as usize;
+                let Some(script_end) = start.checked_add(length).filter(|e| *e <= end) else {
+                    continue;
+                };
+
+                if looks_like_script(&input[start..script_end], endianness) {
+                    return Some(Self::new(kind, endianness, input, start..script_end));
+                }
+            }
+        }
+
+        script_at(UE3_EXPORT_HEADER_SIZE)
+            .map(|endianness| Self::new(kind, endianness, input, UE3_EXPORT_HEADER_SIZE..end))
+    }
+
+    /// Keeps the data around `script` in a container of unknown format, which has no length fields until they are added
+    pub fn sliced(input: &[u8], script: std::ops::Range<usize>, endianness: Endianness) -> Self {
+        Self {
+            kind: ContainerKind::Sliced,
+            endianness,
+            prefix: input[..script.start].to_vec(),
+            suffix: input[script.end..].to_vec(),
+            length_fields: Vec::new(),
+        }
+    }
+
+    /// Adds a field to update when wrapping a script, unless there already is one at `field.offset`.
+    /// Returns `false` if the field doesn't fit inside of the prefix
+    pub fn add_length_field(&mut self, field: LengthField) -> bool {
+        if field.offset + 4 > self.prefix.len() {
+            return false;
+        }
+
+        if !self.length_fields.iter().any(|f| f.offset == field.offset) {
+            self.length_fields.push(field);
+        }
+
+        true
+    }
+
+    fn new(
+        kind: ContainerKind,
+        endianness: Endianness,
+        input: &[u8],
+        script: std::ops::Range<usize>,
+    ) -> Self {
+        let prefix = input[..script.start].to_vec();
+        let script_len = script.len() as u32;
+
+        // the array length right in front of the script, and in UE4 exports the size of the property tag
+        // holding the array, which also counts the length
+        let mut candidates = vec![(prefix.len().checked_sub(4), 0)];
+        if kind == ContainerKind::Ue4Export {
+            let tag_size = prefix.len().checked_sub(4 + UE4_TAG_FIELDS_AFTER_SIZE + 4);
+            candidates.push((tag_size, 4));
+        }
+
+        let length_fields = candidates
+            .into_iter()
+            .filter_map(|(offset, extra)| {
+                let offset = offset?;
+                (read_u32(endianness, &prefix[offset..offset + 4]) == script_len + extra)
+                    .then_some(LengthField { offset, extra })
+            })
+            .collect();
+
+        Self {
+            kind,
+            endianness,
+            prefix,
+            suffix: input[script.end..].to_vec(),
+            length_fields,
+        }
+    }
+
+    /// Byte range of the script inside of the container it was detected in
+    pub fn script_range(&self, container_len: usize) -> std::ops::Range<usize> {
+        self.prefix.len()..container_len - self.suffix.len()
+    }
+
+    /// Puts a script back into the container, updating every length field for its new size
+    pub fn wrap(&self, script: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.prefix.len() + script.len() + self.suffix.len());
+        out.extend_from_slice(&self.prefix);
+
+        for field in &self.length_fields {
+            let value = script.len() as u32 + field.extra;
+            let bytes = &mut out[field.offset..field.offset + 4];
+
+            match self.endianness {
+                Endianness::Little => LittleEndian::write_u32(bytes, value),
+                Endianness::Big => BigEndian::write_u32(bytes, value),
+            }
+        }
+
+        out.extend_from_slice(script);
+        out.extend_from_slice(&self.suffix);
+
+        out
+    }
+}
+
+fn read_u32(endianness: Endianness, bytes: &[u8]) -> u32 {
+    match endianness {
+        Endianness::Little => LittleEndian::read_u32(bytes),
+        Endianness::Big => BigEndian::read_u32(bytes),
+    }
+}
+
+/// Checks whether data starts with a plausible jump table, without needing a config
+fn looks_like_script(script: &[u8], endianness: Endianness) -> bool {
+    let Some(count) = script.get(..4).map(|b| read_u32(endianness, b) as usize) else {
+        return false;
+    };
+
+    let table_end = count
+        .checked_mul(JUMP_ENTRY_SIZE)
+        .and_then(|size| size.checked_add(4));
+    let Some(data_len) = table_end.and_then(|end| script.len().checked_sub(end)) else {
+        return false;
+    };
+
+    if count == 0 {
+        return false;
+    }
+
+    script[4..]
+        .chunks_exact(JUMP_ENTRY_SIZE)
+        .take(count.min(CHECKED_JUMP_ENTRIES))
+        .all(|entry| {
+            let (name, offset) = entry.split_at(JUMP_ENTRY_NAME_SIZE);
+            let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+
+            len > 0
+                && name[..len].iter().all(|b| (0x20..0x7F).contains(b))
+                && name[len..].iter().all(|b| *b == 0)
+                && (read_u32(endianness, offset) as usize) < data_len
+        })
+}
+
+fn to_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode_upper(bytes))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    hex::decode(string).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::container::{
+        Container, ContainerKind, LengthField, UE3_EXPORT_HEADER_SIZE, UE4_PACKAGE_TAG,
+    };
+    use crate::fixture::{bytes, STATES};
+    use crate::Endianness;
+    use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+    #[test]
+    fn ue4_export_round_trip() {
+        let script = bytes(STATES);
+
+        // tagged property header: the property size includes the array length before the data
+        let mut uexp = vec![0xAB; 0x19];
+        // an unrelated value that happens to equal the script size isn't a length field
+        uexp[..4].copy_from_slice(&(script.len() as u32).to_le_bytes());
+        uexp.extend_from_slice(&(script.len() as u32 + 4).to_le_bytes());
+        uexp.extend_from_slice(&[0; 13]);
+        uexp.extend_from_slice(&(script.len() as u32).to_le_bytes());
+        uexp.extend_from_slice(&script);
+        uexp.extend_from_slice(&UE4_PACKAGE_TAG.to_le_bytes());
+
+        let container = Container::detect(&uexp).unwrap();
+        assert_eq!(container.kind, ContainerKind::Ue4Export);
+        assert_eq!(&uexp[container.script_range(uexp.len())], script.as_slice());
+        assert_eq!(container.length_fields.len(), 2);
+        assert_eq!(container.wrap(&script), uexp);
+
+        // a bigger script updates both length fields
+        let bigger = bytes(&format!("{STATES}beginState: s32'CmnActJump'\nendState:\n"));
+        let rewrapped = container.wrap(&bigger);
+        let rewrapped_container = Container::detect(&rewrapped).unwrap();
+        assert_eq!(
+            &rewrapped[rewrapped_container.script_range(rewrapped.len())],
+            bigger.as_slice()
+        );
+        assert_eq!(
+            LittleEndian::read_u32(&rewrapped[0x19..]),
+            bigger.len() as u32 + 4
+        );
+        assert_eq!(&rewrapped[..4], &uexp[..4]);
+    }
+
+    #[test]
+    fn ue3_export_without_length() {
+        let script = bytes(STATES);
+
+        let mut upk = vec![0xFF; UE3_EXPORT_HEADER_SIZE];
+        upk.extend_from_slice(&script);
+
+        let container = Container::detect(&upk).unwrap();
+        assert_eq!(container.kind, ContainerKind::Ue3Export);
+        assert_eq!(container.prefix.len(), UE3_EXPORT_HEADER_SIZE);
+        assert_eq!(container.wrap(&script), upk);
+
+        // plain scripts aren't treated as containers
+        assert!(Container::detect(&script).is_none());
+    }
+
+    #[test]
+    fn sliced_length_fields() {
+        let script = bytes(STATES);
+
+        let mut file = b"HEAD".to_vec();
+        file.extend_from_slice(&(script.len() as u32 + 2).to_be_bytes());
+        file.extend_from_slice(&script);
+        file.extend_from_slice(b"TAIL");
+
+        let mut container = Container::sliced(&file, 8..file.len() - 4, Endianness::Big);
+        assert!(container.add_length_field(LengthField {
+            offset: 4,
+            extra: 2
+        }));
+        assert!(!container.add_length_field(LengthField {
+            offset: 5,
+            extra: 0
+        }));
+        assert_eq!(container.wrap(&script), file);
+
+        let bigger = bytes(&format!("{STATES}beginState: s32'CmnActJump'\nendState:\n"));
+        let rewrapped = container.wrap(&bigger);
+        assert_eq!(&rewrapped[..4], b"HEAD");
+        assert_eq!(
+            BigEndian::read_u32(&rewrapped[4..]),
+            bigger.len() as u32 + 2
+        );
+        assert!(rewrapped.ends_with(b"TAIL"));
+    }
+}
@@ -0,0 +1,160 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use serde::Serialize;
+
+use std::collections::HashSet;
+
+use crate::game_config::ScriptConfig;
+use crate::parser::{InstructionIdentifier, ParseRecovery};
+use crate::{Endianness, SupportedGame};
+
+/// How well a script decodes with one game config in one byte order
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub game: SupportedGame,
+    pub endianness: Endianness,
+    /// Share of the script data that decodes before anything can't be decoded, from 0 to 1
+    pub decoded: f32,
+    /// Share of jump table entries pointing at a decoded instruction of a type that belongs in the jump table, from 0 to 1
+    pub jump_table_match: f32,
+    /// Combined score used for ranking candidates, from 0 to 1
+    pub score: f32,
+}
+
+/// Every supported game config in both byte orders, ranked by how well a script decodes with them
+#[derive(Debug, Clone, Serialize)]
+pub struct Detection {
+    /// Candidates sorted from best to worst
+    pub candidates: Vec<Candidate>,
+}
+
+impl Detection {
+    /// The best candidate, if the script decoded with any config at all
+    pub fn best(&self) -> Option<&Candidate> {
+        self.candidates.first().filter(|c| c.score > 0.0)
+    }
+
+    /// How much the best candidate stands out from the runner-up, from 0 to 1.
+    /// Games sharing the same config aren't counted as runner-ups, as they decode identically
+    pub fn confidence(&self) -> f32 {
+        let Some(best) = self.best() else {
+            return 0.0;
+        };
+
+        let runner_up = self
+            .candidates
+            .iter()
+            .skip(1)
+            .find(|c| {
+                c.game.config_source() != best.game.config_source()
+                    || c.endianness != best.endianness
+            })
+            .map_or(0.0, |c| c.score);
+
+        best.score - runner_up
+    }
+}
+
+/// Tries every embedded config in both byte orders on a script
+pub fn detect_game(input: impl AsRef<[u8]>) -> Detection {
+    let input = input.as_ref();
+
+    let mut candidates = Vec::with_capacity(SupportedGame::ALL.len() * 2);
+    for game in SupportedGame::ALL {
+        let config = game.into_config();
+
+        candidates.push(score::<LittleEndian>(&config, game, input));
+        candidates.push(score::<BigEndian>(&config, game, input));
+    }
+
+    // stable sort keeps the order of `SupportedGame::ALL` for ties
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    Detection { candidates }
+}
+
+fn score<B: ByteOrder>(config: &ScriptConfig, game: SupportedGame, input: &[u8]) -> Candidate {
+    let mut candidate = Candidate {
+        game,
+        endianness: Endianness::of::<B>(),
+        decoded: 0.0,
+        jump_table_match: 0.0,
+        score: 0.0,
+    };
+
+    let Ok((jump_table, base)) = config.read_jump_table::<B>(input) else {
+        return candidate;
+    };
+    let Ok(program) = config.parse_located_with::<B>(input, ParseRecovery::RawTail) else {
+        return candidate;
+    };
+
+    let data_len = input.len() - base;
+    let decoded_len: usize = program
+        .iter()
+        .filter(|i| i.instruction.identifier != InstructionIdentifier::RawData)
+        .map(|i| i.size)
+        .sum();
+
+    // offsets of every decoded instruction that can be the target of a jump table entry
+    let jump_targets: HashSet<usize> = program
+        .iter()
+        .filter(|i| {
+            config
+                .get_by_identifier(&i.instruction.identifier)
+                .is_ok_and(|info| config.is_jump_entry_id(info.id()))
+        })
+        .map(|i| i.offset)
+        .collect();
+    let matched = jump_table
+        .iter()
+        .filter(|e| jump_targets.contains(&(e.offset as usize)))
+        .count();
+
+    candidate.decoded = if data_len == 0 {
+        0.0
+    } else {
+        decoded_len as f32 / data_len as f32
+    };
+    candidate.jump_table_match = if jump_table.is_empty() {
+        0.0
+    } else {
+        matched as f32 / jump_table.len() as f32
+    };
+    candidate.score = (candidate.decoded + candidate.jump_table_match) / 2.0;
+
+    candidate
+}
+
+#[cfg(test)]
+mod test {
+    use crate::detect::detect_game;
+    use crate::{rebuild_bbscript, Endianness, SupportedGame};
+    use byteorder::BigEndian;
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  ifOperation: (IS_GREATER), Mem(Tmp), Val(2)
+    exitState:
+  endIf:
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+  addPositionX: 1000, 0
+endState:
+";
+
+    #[test]
+    fn detect_ggst_big_endian() {
+        let bytes = rebuild_bbscript::<BigEndian>(SupportedGame::Ggst.into_config(), SCRIPT.into())
+            .unwrap();
+
+        let detection = detect_game(&bytes);
+        let best = detection.best().unwrap();
+
+        assert_eq!(best.game, SupportedGame::Ggst);
+        assert_eq!(best.endianness, Endianness::Big);
+        assert_eq!(best.score, 1.0);
+        assert!(detection.confidence() > 0.0);
+    }
+}
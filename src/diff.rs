@@ -0,0 +1,368 @@
+use serde::Serialize;
+
+use crate::ast::{NodeId, NodeKind, Script};
+use crate::error::BBScriptError;
+use crate::game_config::ScriptConfig;
+use crate::parser::{arg_to_string, instruction_name, InstructionValue};
+use crate::HashMap;
+
+/// Differences between two versions of a script, matching states and subroutines by name
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScriptDiff {
+    pub added: Vec<NamedNode>,
+    pub removed: Vec<NamedNode>,
+    pub modified: Vec<NodeDiff>,
+}
+
+impl ScriptDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NamedNode {
+    pub kind: NodeKind,
+    pub name: String,
+}
+
+/// A state or subroutine found in both scripts with different instructions
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeDiff {
+    pub kind: NodeKind,
+    pub name: String,
+    pub changes: Vec<InstructionChange>,
+}
+
+/// A change to an instruction, with indices counting from the instruction opening the state
+#[derive(Debug, Clone, Serialize)]
+pub enum InstructionChange {
+    Added {
+        new_index: usize,
+        instruction: String,
+    },
+    Removed {
+        old_index: usize,
+        instruction: String,
+    },
+    /// The same instruction with different args
+    Modified {
+        old_index: usize,
+        new_index: usize,
+        name: String,
+        args: Vec<ArgChange>,
+    },
+}
+
+/// An arg that differs between two versions of an instruction, `None` if it only exists in one of them
+#[derive(Debug, Clone, Serialize)]
+pub struct ArgChange {
+    pub index: usize,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// An instruction rendered the same way as in readable scripts, so enums and variables are compared by name
+#[derive(PartialEq)]
+pub(crate) struct Line {
+    name: String,
+    args: Vec<String>,
+}
+
+impl Line {
+    fn new(config: &ScriptConfig, instruction: &InstructionValue) -> Result<Self, BBScriptError> {
+        Ok(Self {
+            name: instruction_name(config, &instruction.identifier),
+            args: instruction
+                .args
+                .iter()
+                .map(|arg| arg_to_string(config, arg))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub(crate) fn text(&self) -> String {
+        format!("{}: {}", self.name, self.args.join(", "))
+    }
+}
+
+pub(crate) enum Edit {
+    /// Indices of an instruction in both versions
+    Equal(usize, usize),
+    Remove(usize),
+    Add(usize),
+}
+
+/// Compares two scripts parsed with the same config.
+/// States and subroutines are matched by kind and name, and by order for names that appear more than once
+pub fn diff_scripts(
+    config: &ScriptConfig,
+    old: &Script,
+    new: &Script,
+) -> Result<ScriptDiff, BBScriptError> {
+    let old_nodes = named_roots(old);
+    let new_nodes = named_roots(new);
+
+    let mut diff = ScriptDiff::default();
+
+    for (key, new_id) in &new_nodes {
+        let (kind, name, _) = key.clone();
+
+        let Some((_, old_id)) = old_nodes.iter().find(|(k, _)| k == key) else {
+            diff.added.push(NamedNode { kind, name });
+            continue;
+        };
+
+        let changes =
+            diff_instructions(&lines(config, old, *old_id)?, &lines(config, new, *new_id)?);
+        if !changes.is_empty() {
+            diff.modified.push(NodeDiff {
+                kind,
+                name,
+                changes,
+            });
+        }
+    }
+
+    for (key, _) in &old_nodes {
+        if !new_nodes.iter().any(|(k, _)| k == key) {
+            let (kind, name, _) = key.clone();
+            diff.removed.push(NamedNode { kind, name });
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Key of a named state or subroutine, including how many nodes with the same name came before it
+pub(crate) type NodeKey = (NodeKind, String, usize);
+
+pub(crate) fn named_roots(script: &Script) -> Vec<(NodeKey, NodeId)> {
+    let mut occurrences: HashMap<(NodeKind, &str), usize> = HashMap::new();
+
+    script
+        .roots()
+        .iter()
+        .filter_map(|id| {
+            let node = script.node(*id);
+            let name = node
+                .name()
+                .filter(|_| matches!(node.kind, NodeKind::State | NodeKind::Subroutine))?;
+
+            let occurrence = occurrences.entry((node.kind, name)).or_default();
+            let key = (node.kind, name.to_string(), *occurrence);
+            *occurrence += 1;
+
+            Some((key, *id))
+        })
+        .collect()
+}
+
+pub(crate) fn lines(
+    config: &ScriptConfig,
+    script: &Script,
+    id: NodeId,
+) -> Result<Vec<Line>, BBScriptError> {
+    script
+        .node_instructions(id)
+        .into_iter()
+        .map(|i| Line::new(config, &i.instruction))
+        .collect()
+}
+
+fn diff_instructions(old: &[Line], new: &[Line]) -> Vec<InstructionChange> {
+    let edits = edit_script(old, new);
+    let mut changes = Vec::new();
+
+    // removals and additions between two unchanged instructions, which are paired up by name if possible
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    for edit in edits {
+        match edit {
+            Edit::Remove(index) => removed.push(index),
+            Edit::Add(index) => added.push(index),
+            Edit::Equal(..) => {
+                pair_changes(old, new, &removed, &added, &mut changes);
+                removed.clear();
+                added.clear();
+            }
+        }
+    }
+    pair_changes(old, new, &removed, &added, &mut changes);
+
+    changes
+}
+
+fn pair_changes(
+    old: &[Line],
+    new: &[Line],
+    removed: &[usize],
+    added: &[usize],
+    changes: &mut Vec<InstructionChange>,
+) {
+    let mut next_added = 0;
+
+    for &old_index in removed {
+        let paired = added[next_added..]
+            .iter()
+            .position(|new_index| new[*new_index].name == old[old_index].name);
+
+        let Some(position) = paired else {
+            changes.push(InstructionChange::Removed {
+                old_index,
+                instruction: old[old_index].text(),
+            });
+            continue;
+        };
+
+        // additions skipped over to reach the pair come before it
+        for &new_index in &added[next_added..next_added + position] {
+            changes.push(InstructionChange::Added {
+                new_index,
+                instruction: new[new_index].text(),
+            });
+        }
+
+        let new_index = added[next_added + position];
+        next_added += position + 1;
+
+        let (old_args, new_args) = (&old[old_index].args, &new[new_index].args);
+        let args = (0..old_args.len().max(new_args.len()))
+            .filter(|i| old_args.get(*i) != new_args.get(*i))
+            .map(|index| ArgChange {
+                index,
+                old: old_args.get(index).cloned(),
+                new: new_args.get(index).cloned(),
+            })
+            .collect();
+
+        changes.push(InstructionChange::Modified {
+            old_index,
+            new_index,
+            name: old[old_index].name.clone(),
+            args,
+        });
+    }
+
+    for &new_index in &added[next_added..] {
+        changes.push(InstructionChange::Added {
+            new_index,
+            instruction: new[new_index].text(),
+        });
+    }
+}
+
+/// Longest common subsequence of two instruction lists, as the edits turning `old` into `new`.
+/// Unchanged instructions at the start and end are skipped before building the table
+pub(crate) fn edit_script<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let (n, m) = (old_middle.len(), new_middle.len());
+
+    // lengths of the common subsequence of old_middle[i..] and new_middle[j..]
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[at(i, j)] = if old_middle[i] == new_middle[j] {
+                table[at(i + 1, j + 1)] + 1
+            } else {
+                table[at(i + 1, j)].max(table[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_middle[i] == new_middle[j] {
+            edits.push(Edit::Equal(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || table[at(i, j + 1)] >= table[at(i + 1, j)]) {
+            edits.push(Edit::Add(prefix + j));
+            j += 1;
+        } else {
+            edits.push(Edit::Remove(prefix + i));
+            i += 1;
+        }
+    }
+    edits.extend((0..suffix).map(|i| Edit::Equal(old.len() - suffix + i, new.len() - suffix + i)));
+
+    edits
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::NodeKind;
+    use crate::diff::{diff_scripts, InstructionChange};
+    use crate::fixture::bytes;
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    const OLD: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  ifOperation: (IS_GREATER), Mem(Tmp), Val(2)
+    exitState:
+  endIf:
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+endState:
+";
+
+    const NEW: &str = r"
+beginState: s32'CmnActJump'
+endState:
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 6
+  addPositionX: 1000, 0
+  ifOperation: (IS_LESSER), Mem(Tmp), Val(2)
+    exitState:
+  endIf:
+endState:
+";
+
+    #[test]
+    fn diff_states() {
+        let config = SupportedGame::Ggst.into_config();
+        let parse = |source: &str| {
+            let bytes = bytes(source);
+            config.parse_tree::<LittleEndian>(&bytes).unwrap()
+        };
+
+        let diff = diff_scripts(&config, &parse(OLD), &parse(NEW)).unwrap();
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "CmnActJump");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name, "CmnActCrouch");
+
+        let stand = &diff.modified[0];
+        assert_eq!(
+            (stand.kind, stand.name.as_str()),
+            (NodeKind::State, "CmnActStand")
+        );
+        assert!(matches!(
+            &stand.changes[..],
+            [
+                InstructionChange::Modified { old_index: 1, new_index: 1, args: sprite, .. },
+                InstructionChange::Added { new_index: 2, instruction },
+                InstructionChange::Modified { old_index: 2, new_index: 3, args: if_op, .. },
+            ] if sprite[0].new.as_deref() == Some("6")
+                && instruction == "addPositionX: 1000, 0"
+                && if_op[0].old.as_deref() == Some("(IS_GREATER)")
+                && if_op[0].new.as_deref() == Some("(IS_LESSER)")
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum BBScriptError {
     #[error("Failed to open game config file `{0}` with error `{1}`")]
     ConfigOpenError(String, String),
@@ -18,6 +19,8 @@ pub enum BBScriptError {
     UnknownInstructionID(u32),
     #[error("No variable ID associated with `{0}` in config")]
     NoVariableName(String),
+    #[error("No constant named `{0}` is declared")]
+    NoConstantName(String),
     #[error("No enum associated with index argument {0} in instruction {1}`")]
     NoEnum(usize, u32),
     #[error("Argument tried to access nonexistant enum `{0}`")]
@@ -28,6 +31,42 @@ pub enum BBScriptError {
         "Jump table size of `{0}` is too big! Is the program reading from the correct offset?"
     )]
     IncorrectJumpTableSize(String),
+    #[error("Operation requires a config with sized instructions")]
+    UnsizedConfig,
+    #[error("Script ended at offset {0:#X} while reading the jump table")]
+    JumpTableEof(usize),
+    #[error("Script ended at offset {0:#X} while reading an instruction ID")]
+    InstructionEof(usize),
+    #[error("Script ended at offset {0:#X} while reading the size of instruction {1}")]
+    InstructionSizeEof(usize, u32),
+    #[error("Script ended at offset {0:#X} while reading argument {2} of instruction {1}")]
+    ArgumentEof(usize, u32, usize),
+    #[error("Instruction {1} at offset {0:#X} has size {2}, which is too small for the arguments in config")]
+    InstructionSizeTooSmall(usize, u32, usize),
+    #[error("Invalid raw data instruction: {0}")]
+    InvalidRawData(String),
+    #[error("Invalid jump table directive: {0}")]
+    InvalidJumpTable(String),
+    #[error("No end instruction known for blocks opened by `{0}`, add one to `block_ends` in the config")]
+    NoBlockEnd(String),
+    #[error("Invalid expression: {0}")]
+    InvalidExpression(String),
+    #[error("Invalid include or macro at {0}: {1}")]
+    InvalidMacro(String, String),
+    #[error("Invalid alias declaration: {0}")]
+    InvalidAlias(String),
+    #[error("Invalid header directive: {0}")]
+    InvalidHeader(String),
+    #[error("Invalid uasset/uexp pair: {0}")]
+    InvalidUasset(String),
+    #[error("Invalid FPAC archive: {0}")]
+    InvalidPac(String),
+    #[error("No state or subroutine named `{0}`")]
+    StateNotFound(String),
+    #[error("Invalid patch: {0}")]
+    InvalidPatch(String),
+    #[error("State `{0}` was changed upstream since the patch was made")]
+    PatchConflict(String),
     #[error("Got instruction `{0}` mismatched to size {1}. size defined in config is {2}")]
     IncorrectFunctionSize(String, usize, usize),
     #[error(transparent)]
@@ -0,0 +1,18 @@
+use byteorder::LittleEndian;
+
+use crate::{rebuild_bbscript, SupportedGame};
+
+/// Two states with a sprite each, which most tests start from
+pub(crate) const STATES: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+endState:
+";
+
+/// Assembles a readable script with the GGST config, in little endian
+pub(crate) fn bytes(source: &str) -> Vec<u8> {
+    rebuild_bbscript::<LittleEndian>(SupportedGame::Ggst.into_config(), source.into()).unwrap()
+}
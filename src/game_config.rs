@@ -4,7 +4,9 @@ use ron::de;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
-use crate::HashMap;
+use crate::parser::JumpEntry;
+use crate::{fnv1a_hash, HashMap, ScriptAliases, SupportedGame};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
@@ -12,7 +14,7 @@ use std::path::Path;
 pub type BBSNumber = i32;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(transparent)] 
+#[serde(transparent)]
 pub struct SizedString<const N: usize>(pub String);
 
 impl<const N: usize> std::fmt::Display for SizedString<N> {
@@ -47,7 +49,7 @@ pub enum ArgType {
     /// The first `i32` is the tag, which is typically `0` for a literal value, and `2` for a variable ID
     ///
     /// `AccessedValue`s are treated specially, the value
-    /// they contain will be translated to a corresponding name using the `variable_config` field in the [`GameDB`]
+    /// they contain will be translated to a corresponding name using the `named_variables` field in the [`ScriptConfig`]
     AccessedValue,
 }
 
@@ -74,7 +76,7 @@ impl ArgType {
 pub enum TaggedValue {
     Literal(BBSNumber),
     Variable(BBSNumber),
-    /// A tagged value whos tag does not match either specified value in the [`GameDB`]
+    /// A tagged value whos tag does not match either specified value in the [`ScriptConfig`]
     Improper {
         tag: BBSNumber,
         value: BBSNumber,
@@ -178,10 +180,90 @@ pub struct ScriptConfig {
     /// A map that allows associating names with specific values of a [`TaggedValue::Variable`]
     #[serde(serialize_with = "ordered_bimap")]
     pub named_variables: BiMap<BBSNumber, String>,
-    /// A map of [`BBSArgType::Enum`] maps for naming specific values
+    /// A map of [`ArgType::Enum`] maps for naming specific values
     #[serde(serialize_with = "ordered_enums")]
     pub named_value_maps: HashMap<String, BiMap<BBSNumber, String>>,
+    /// How the jump table is built for scripts that don't record their original one
+    #[serde(default)]
+    pub jump_table: JumpTableOptions,
+    /// Instructions that are written as infix expressions in readable scripts
+    #[serde(default)]
+    pub expressions: ExpressionSyntax,
+    /// The instruction closing blocks opened by each instruction, by name.
+    /// Used for blocks written with braces in readable scripts
+    #[serde(default)]
+    pub block_ends: BTreeMap<String, String>,
     pub(crate) instructions: InstructionInfo,
+    /// Names declared by readable scripts rather than the config, used when parsing.
+    /// They aren't part of the [`ScriptConfig::content_hash`], since the scripts declare them themselves
+    #[serde(skip)]
+    pub aliases: ScriptAliases,
+    /// The game this config is embedded for, if it was taken from [`SupportedGame::into_config`]
+    #[serde(skip)]
+    pub(crate) game: Option<SupportedGame>,
+}
+
+/// Strategy for building a jump table from the states of a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JumpTableOptions {
+    /// Only add the first state with each name, even if the names are in different tables
+    #[serde(default = "default_deduplicate")]
+    pub deduplicate: bool,
+    #[serde(default)]
+    pub order: JumpTableOrder,
+}
+
+impl Default for JumpTableOptions {
+    fn default() -> Self {
+        Self {
+            deduplicate: default_deduplicate(),
+            order: JumpTableOrder::default(),
+        }
+    }
+}
+
+fn default_deduplicate() -> bool {
+    true
+}
+
+/// Instructions taking an operation and two [`ArgType::AccessedValue`]s, written as infix expressions.
+/// Operations without an operator are written as normal instructions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpressionSyntax {
+    /// The [`ArgType::Enum`] holding the operation
+    #[serde(default)]
+    pub operation_enum: String,
+    /// Instructions written as `keyword (a > b)`, mapped to their keyword
+    #[serde(default)]
+    pub conditions: BTreeMap<String, String>,
+    /// The instruction written as `a += b`
+    #[serde(default)]
+    pub assignment: Option<String>,
+    /// Operators used in conditions, by the name of their operation
+    #[serde(default)]
+    pub condition_operators: BTreeMap<String, String>,
+    /// Operators used in assignments, by the name of their operation
+    #[serde(default)]
+    pub assignment_operators: BTreeMap<String, String>,
+}
+
+impl ExpressionSyntax {
+    /// Index of the operation arg of an expression instruction, which is followed by its two operands
+    pub(crate) fn operation_index(&self, args: &[ArgType]) -> Option<usize> {
+        args.windows(3).position(|window| {
+            matches!(window, [ArgType::Enum(name), ArgType::AccessedValue, ArgType::AccessedValue] if *name == self.operation_enum)
+        })
+    }
+}
+
+/// Order of the entries within each jump table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JumpTableOrder {
+    /// The order the states appear in the script
+    #[default]
+    Script,
+    /// Sorted by name, for games that binary search the table
+    Name,
 }
 
 impl ScriptConfig {
@@ -275,7 +357,31 @@ impl ScriptConfig {
     }
 
     pub fn get_variable_by_name(&self, variable_name: String) -> Option<BBSNumber> {
-        self.named_variables.get_by_right(&variable_name).copied()
+        self.aliases
+            .variables
+            .get_by_right(&variable_name)
+            .or_else(|| self.named_variables.get_by_right(&variable_name))
+            .copied()
+    }
+
+    /// Name of the variable `Mem(id)`, taken from the aliases before `named_variables`
+    pub fn variable_name(&self, id: BBSNumber) -> Option<&String> {
+        self.aliases
+            .variables
+            .get_by_left(&id)
+            .or_else(|| self.named_variables.get_by_left(&id))
+    }
+
+    /// The game this config is embedded for, `None` for configs loaded from a file
+    pub fn game(&self) -> Option<SupportedGame> {
+        self.game
+    }
+
+    /// Hash of the config's contents, which is the same for configs that decode scripts the same way
+    pub fn content_hash(&self) -> u64 {
+        // maps are serialized in order, so this doesn't change between runs
+        let serialized = ron::ser::to_string(self).unwrap_or_default();
+        fnv1a_hash(serialized.into_bytes())
     }
 
     pub fn is_unsized(&self) -> bool {
@@ -286,6 +392,93 @@ impl ScriptConfig {
     pub fn is_jump_entry_id(&self, id: u32) -> bool {
         self.jump_table_ids.contains(&id)
     }
+
+    /// Builds the jump table for a script from every state that can have an entry, in script order,
+    /// following the config's [`JumpTableOptions`]. Entries are grouped by table in the order of `jump_table_ids`
+    pub fn generate_jump_table(&self, states: Vec<JumpEntry>) -> Vec<JumpEntry> {
+        self.generate_jump_table_with(states, self.jump_table)
+    }
+
+    /// Same as [`ScriptConfig::generate_jump_table`], with `options` used instead of the config's
+    pub fn generate_jump_table_with(
+        &self,
+        states: Vec<JumpEntry>,
+        options: JumpTableOptions,
+    ) -> Vec<JumpEntry> {
+        let mut seen = std::collections::HashSet::new();
+        let mut table: Vec<JumpEntry> = states
+            .into_iter()
+            .filter(|e| !options.deduplicate || seen.insert(e.name.0.clone()))
+            .collect();
+
+        if options.order == JumpTableOrder::Name {
+            table.sort_by(|a, b| a.name.0.cmp(&b.name.0));
+        }
+
+        table.sort_by_key(|e| self.jump_table_ids.iter().position(|id| *id == e.table_id));
+
+        table
+    }
+
+    /// Copies instruction sizes from `new_sizes` into this config, adding any instructions it doesn't have yet.
+    /// Args of resized instructions are filled with [`ArgType::Number`] if small enough, otherwise cleared
+    pub fn update_sizes(&mut self, new_sizes: ScriptConfig) -> Result<(), BBScriptError> {
+        let new_sizes = match new_sizes.instructions {
+            InstructionInfo::Sized(instructions) => instructions,
+            InstructionInfo::Unsized(_) => return Err(BBScriptError::UnsizedConfig),
+        };
+
+        let main_config_instructions = match self.instructions {
+            InstructionInfo::Sized(ref mut instructions) => instructions,
+            InstructionInfo::Unsized(_) => return Err(BBScriptError::UnsizedConfig),
+        };
+
+        for (id, mut new_info) in new_sizes.into_iter() {
+            if let Some(instruction) = main_config_instructions.get_mut(&id) {
+                log::trace!("checking id {}", id);
+                if instruction.size != new_info.size {
+                    log::info!("updating id {}", id);
+                    instruction.resize(new_info.size);
+                }
+            } else {
+                log::info!("adding new instruction {} with size {}", id, new_info.size);
+                if new_info.size < 20 {
+                    new_info.set_args(&vec![ArgType::Number; (new_info.size - 4) / 4]);
+                }
+                main_config_instructions.insert(id, new_info);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the size of a single instruction, adding it to the config if it doesn't exist.
+    /// Args are updated the same way as [`ScriptConfig::update_sizes`]
+    pub fn set_size(&mut self, id: u32, size: usize) -> Result<(), BBScriptError> {
+        let instructions = match self.instructions {
+            InstructionInfo::Sized(ref mut instructions) => instructions,
+            InstructionInfo::Unsized(_) => return Err(BBScriptError::UnsizedConfig),
+        };
+
+        match instructions.get_mut(&id) {
+            Some(instruction) if instruction.size != size => instruction.resize(size),
+            Some(_) => {}
+            None => {
+                let mut instruction = SizedInstruction {
+                    size: 0,
+                    name: String::new(),
+                    code_block: CodeBlock::NoBlock,
+                    args: SmallVec::new(),
+                    description: String::new(),
+                };
+                instruction.resize(size);
+
+                instructions.insert(id, instruction);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -311,7 +504,7 @@ impl SizedInstruction {
 
         let mut args = self.args.clone();
 
-        if known_args_size != (self.size - INSTRUCTION_SIZE) {
+        if known_args_size != self.size.saturating_sub(INSTRUCTION_SIZE) {
             // size has an extra 4 bytes because of the ID being a u32
             // we use saturating_sub to allow known args to be larger than labeled size.
             // but still warn for it in some earlier initial sanity checks
@@ -326,6 +519,19 @@ impl SizedInstruction {
     pub fn set_args(&mut self, args: &[ArgType]) {
         self.args = SmallVec::from(args);
     }
+
+    /// Changes the size, filling args with [`ArgType::Number`] if small enough, otherwise clearing them
+    fn resize(&mut self, size: usize) {
+        self.size = size;
+
+        if size < 20 {
+            log::debug!("args are small enough to fill with Number");
+            self.set_args(&vec![ArgType::Number; size.saturating_sub(4) / 4]);
+        } else {
+            log::debug!("clearing args");
+            self.set_args(&[]);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -342,6 +548,12 @@ pub struct UnsizedInstruction {
     pub description: String,
 }
 
+impl Default for UnsizedInstruction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UnsizedInstruction {
     pub fn new() -> Self {
         Self {
@@ -361,7 +573,9 @@ impl UnsizedInstruction {
         }
     }
 
-    pub fn args_with_known_size(&self, dynamic_size: usize) -> SmallVec<[ArgType; 16]> {
+    /// Args for an instance of this instruction with the given size, padded with [`ArgType::Unknown`] if needed.
+    /// Returns `None` if the size is too small to fit the args in the config
+    pub fn args_with_known_size(&self, dynamic_size: usize) -> Option<SmallVec<[ArgType; 16]>> {
         const INSTRUCTION_SIZE: usize = 0x8;
         let known_args_size: usize = self.args.iter().map(|a| a.size()).sum();
 
@@ -369,17 +583,16 @@ impl UnsizedInstruction {
 
         let mut args = self.args.clone();
 
-        if known_args_size > (dynamic_size - INSTRUCTION_SIZE) {
-            panic!("dynamic argument size smaller than argument size in config!")
-        }
+        let left_over = dynamic_size
+            .checked_sub(INSTRUCTION_SIZE)?
+            .checked_sub(known_args_size)?;
 
-        if known_args_size != (dynamic_size - INSTRUCTION_SIZE) {
+        if left_over != 0 {
             // size typically has an extra 4 bytes because of the ID being a u32
-            let left_over = (dynamic_size.saturating_sub(INSTRUCTION_SIZE)) - known_args_size;
             args.push(ArgType::Unknown(left_over))
         }
 
-        args
+        Some(args)
     }
 }
 
@@ -506,7 +719,12 @@ impl Into<ScriptConfig> for GameDB {
             variable_tag: 2,
             named_variables: BiMap::new(),
             named_value_maps: value_maps,
+            jump_table: JumpTableOptions::default(),
+            expressions: ExpressionSyntax::default(),
+            block_ends: BTreeMap::new(),
             instructions: InstructionInfo::Sized(instructions),
+            aliases: ScriptAliases::default(),
+            game: None,
         }
     }
 }
@@ -552,10 +770,7 @@ where
 }
 
 fn is_noblock(codeblock: &CodeBlock) -> bool {
-    match codeblock {
-        CodeBlock::NoBlock => true,
-        _ => false
-    }
+    matches!(codeblock, CodeBlock::NoBlock)
 }
 
 #[cfg(test)]
@@ -677,20 +892,15 @@ pub enum Arg {
     Unknown(u32),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum CodeBlock {
     Begin,
     #[deprecated]
     #[cfg(feature = "old-cfg-converter")]
     BeginJumpEntry,
     End,
+    #[default]
     NoBlock,
     BeginNonrecursive,
     EndState,
 }
-
-impl Default for CodeBlock {
-    fn default() -> Self {
-        Self::NoBlock
-    }
-}
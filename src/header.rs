@@ -0,0 +1,179 @@
+use std::fmt::{Display, Write};
+
+use byteorder::ByteOrder;
+
+use crate::container::Container;
+use crate::game_config::{JumpTableOrder, ScriptConfig};
+use crate::preprocess::INCLUDE_DIRECTIVE;
+use crate::{Endianness, SupportedGame};
+
+pub const GAME_DIRECTIVE: &str = "#game";
+pub const CONFIG_HASH_DIRECTIVE: &str = "#configHash";
+pub const ENDIANNESS_DIRECTIVE: &str = "#endianness";
+pub const CONTAINER_DIRECTIVE: &str = "#container";
+pub const JUMP_TABLE_ORDER_DIRECTIVE: &str = "#jumpTableOrder";
+pub const BLOCKS_DIRECTIVE: &str = "#blocks";
+
+/// How the blocks of a readable script are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockStyle {
+    /// Blocks are only indented, with their end instructions written out
+    #[default]
+    Indented,
+    /// Blocks with a known end instruction are wrapped in `{ }`, which stands for the end instruction
+    Braces,
+}
+
+/// Settings a readable script was parsed with, written as directives at the top of the script
+/// so it can be rebuilt without passing them again
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptHeader {
+    pub game: Option<SupportedGame>,
+    /// [`ScriptConfig::content_hash`] of the config used for parsing
+    pub config_hash: Option<u64>,
+    pub endianness: Option<Endianness>,
+    /// The container the script was taken out of
+    pub container: Option<Container>,
+    pub jump_table_order: Option<JumpTableOrder>,
+    /// Scripts can always be rebuilt with either style, this only decides how they are written
+    pub block_style: Option<BlockStyle>,
+    /// Path of the alias file whose variable names were used for parsing, see [`ScriptAliases`](crate::ScriptAliases).
+    /// Written as an `#include` of the file, so the script declares the same names when it's rebuilt
+    pub aliases: Option<String>,
+}
+
+impl ScriptHeader {
+    /// The header for a script parsed with `config` in byte order `B`
+    pub fn new<B: ByteOrder>(config: &ScriptConfig) -> Self {
+        Self {
+            game: config.game(),
+            config_hash: Some(config.content_hash()),
+            endianness: Some(Endianness::of::<B>()),
+            container: None,
+            jump_table_order: Some(config.jump_table.order),
+            block_style: None,
+            aliases: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for ScriptHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(game) = self.game {
+            writeln!(f, "{GAME_DIRECTIVE}: ({game:?})")?;
+        }
+        if let Some(hash) = self.config_hash {
+            writeln!(f, "{CONFIG_HASH_DIRECTIVE}: 0x{hash:016X}")?;
+        }
+        if let Some(endianness) = self.endianness {
+            writeln!(f, "{ENDIANNESS_DIRECTIVE}: ({endianness:?})")?;
+        }
+        if let Some(order) = self.jump_table_order {
+            writeln!(f, "{JUMP_TABLE_ORDER_DIRECTIVE}: ({order:?})")?;
+        }
+        if let Some(style) = self.block_style {
+            writeln!(f, "{BLOCKS_DIRECTIVE}: ({style:?})")?;
+        }
+        if let Some(container) = &self.container {
+            write!(
+                f,
+                "{CONTAINER_DIRECTIVE}: ({:?}), ({:?}), 0x{}, 0x{}",
+                container.kind,
+                container.endianness,
+                hex::encode_upper(&container.prefix),
+                hex::encode_upper(&container.suffix)
+            )?;
+
+            for field in &container.length_fields {
+                write!(f, ", {}, {}", field.offset, field.extra)?;
+            }
+            f.write_char('\n')?;
+        }
+        // after the directives, since it is replaced by the declarations in the file before rebuilding
+        if let Some(path) = &self.aliases {
+            writeln!(f, "{INCLUDE_DIRECTIVE} \"{path}\"")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::container::{Container, ContainerKind, LengthField};
+    use crate::game_config::JumpTableOrder;
+    use crate::parser::ParseRecovery;
+    use crate::rebuilder::rebuild_readable;
+    use crate::{rebuild_bbscript, Endianness, ScriptHeader, SupportedGame};
+    use byteorder::{BigEndian, LittleEndian};
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+endState:
+";
+
+    #[test]
+    fn header_round_trip() {
+        let config = SupportedGame::Ggst.into_config();
+        let bytes = rebuild_bbscript::<BigEndian>(SupportedGame::Ggst.into_config(), SCRIPT.into())
+            .unwrap();
+
+        let header = ScriptHeader {
+            container: Some(Container {
+                kind: ContainerKind::Ue4Export,
+                endianness: Endianness::Little,
+                prefix: vec![0xAB; 8],
+                suffix: Vec::new(),
+                length_fields: vec![LengthField {
+                    offset: 4,
+                    extra: 0,
+                }],
+            }),
+            ..ScriptHeader::new::<BigEndian>(&config)
+        };
+        let readable = config
+            .parse_to_string_with_header::<BigEndian>(&bytes, 12, ParseRecovery::Strict, &header)
+            .unwrap();
+        assert!(readable.starts_with("#game: (Ggst)\n"));
+
+        let read = ScriptHeader::read(&readable).unwrap();
+        assert_eq!(read, header);
+        assert_eq!(read.config_hash, Some(config.content_hash()));
+
+        // the hash follows changes made to the config after it was first taken
+        let mut changed = SupportedGame::Ggst.into_config();
+        let before = changed.content_hash();
+        changed.named_variables.insert(123456, "Changed".into());
+        assert_ne!(changed.content_hash(), before);
+
+        assert_eq!(read.endianness, Some(Endianness::Big));
+        assert_eq!(read.jump_table_order, Some(JumpTableOrder::Script));
+
+        assert_eq!(
+            rebuild_readable::<BigEndian>(&config, &readable).unwrap(),
+            bytes
+        );
+
+        // scripts without a header still rebuild
+        assert!(ScriptHeader::read(SCRIPT).unwrap().is_empty());
+        assert!(ScriptHeader::read("#game: (Unknown)\n").is_err());
+
+        // the header's jump table order is used over the config's
+        let mut by_name = SupportedGame::Ggst.into_config();
+        by_name.jump_table.order = JumpTableOrder::Name;
+        let script = "beginState: s32'B'\nendState:\nbeginState: s32'A'\nendState:\n";
+        let in_script_order = rebuild_readable::<LittleEndian>(&config, script).unwrap();
+        let readable = config
+            .parse_to_string::<LittleEndian>(&in_script_order, 12)
+            .unwrap();
+        assert_eq!(
+            rebuild_readable::<LittleEndian>(&by_name, &readable).unwrap(),
+            in_script_order
+        );
+    }
+}
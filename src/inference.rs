@@ -0,0 +1,761 @@
+use byteorder::ByteOrder;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::error::BBScriptError;
+use crate::game_config::{
+    ArgType, BBSNumber, CodeBlock, InstructionInfo, ScriptConfig, SizedInstruction,
+};
+use crate::parser::{InstructionIdentifier, ParseRecovery};
+use crate::HashMap;
+
+/// Largest size tried for an instruction whose size isn't known
+const MAX_INFERRED_SIZE: usize = 0x400;
+/// Number of search steps allowed per state before giving up on it
+const SEARCH_BUDGET: usize = 20_000;
+/// Number of ways a state can be decoded before it's too ambiguous to be used
+const MAX_SOLUTIONS: usize = 16;
+/// How far above the highest known ID an unknown instruction ID can be
+const MAX_NEW_ID_DISTANCE: u32 = 0x400;
+const ALIGNMENT: usize = 4;
+
+/// Most distinct values a number can take across a corpus to be proposed as an enum
+const MAX_ENUM_VALUES: usize = 8;
+/// How many times each distinct value has to occur on average before a number is proposed as an enum
+const MIN_ENUM_OCCURRENCES_PER_VALUE: usize = 4;
+/// Strings are only proposed if at least one is longer than a number could be
+const MIN_STRING_LENGTH: usize = 5;
+
+/// Result of inferring the size of a single instruction ID
+#[derive(Debug, Clone, Serialize)]
+pub struct InferredSize {
+    /// The best size found, or `None` if no state could be decoded with this instruction in it
+    pub size: Option<usize>,
+    /// Size of the instruction in the config used for inference, if it had one
+    pub previous_size: Option<usize>,
+    /// Number of states using the instruction that decode with `size`
+    pub votes: usize,
+    /// Number of states using the instruction that still don't decode
+    pub conflicts: usize,
+    /// Share of states using the instruction that decode, from 0 to 1
+    pub confidence: f32,
+}
+
+/// Sizes inferred from a set of scripts for every instruction ID that was unknown or had a wrong size
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SizeInference {
+    pub sizes: BTreeMap<u32, InferredSize>,
+    /// Number of states that decode exactly with the inferred sizes
+    pub decoded_states: usize,
+    pub total_states: usize,
+}
+
+impl SizeInference {
+    /// Writes all inferred sizes with a size into the config
+    pub fn apply(&self, config: &mut ScriptConfig) -> Result<(), BBScriptError> {
+        for (id, inferred) in &self.sizes {
+            if let Some(size) = inferred.size {
+                config.set_size(*id, size)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Typed args proposed for an instruction that had [`ArgType::Unknown`] data in its config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArgProposal {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Number of times the instruction was found in the scripts
+    pub occurrences: usize,
+    /// The full arg list replacing the one in the config
+    pub args: Vec<ArgType>,
+    /// Values seen for each new [`ArgType::Enum`] in `args`, so they can be named
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub enums: BTreeMap<String, BTreeSet<BBSNumber>>,
+}
+
+/// A config patch with arg types proposed for instructions with unknown data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArgPatch {
+    pub instructions: BTreeMap<u32, ArgProposal>,
+}
+
+impl ArgPatch {
+    /// Replaces the args of every patched instruction, adding an empty value map for each new enum
+    pub fn apply(&self, config: &mut ScriptConfig) -> Result<(), BBScriptError> {
+        let InstructionInfo::Sized(id_map) = &mut config.instructions else {
+            return Err(BBScriptError::UnsizedConfig);
+        };
+
+        for (id, proposal) in &self.instructions {
+            let Some(instruction) = id_map.get_mut(id) else {
+                log::warn!("skipping args for instruction {id} missing from the config");
+                continue;
+            };
+
+            let args_size: usize = proposal.args.iter().map(|a| a.size()).sum();
+            if args_size + ALIGNMENT != instruction.size {
+                log::warn!(
+                    "skipping args for instruction {id}, they take {args_size:#X} bytes but the instruction has {:#X}",
+                    instruction.size.saturating_sub(ALIGNMENT)
+                );
+                continue;
+            }
+
+            instruction.set_args(&proposal.args);
+        }
+
+        for name in self.instructions.values().flat_map(|p| p.enums.keys()) {
+            config.named_value_maps.entry(name.clone()).or_default();
+        }
+
+        Ok(())
+    }
+}
+
+impl ScriptConfig {
+    /// Proposes typed args for the [`ArgType::Unknown`] data of sized instructions,
+    /// by looking at the data of every occurrence of the instruction in a set of scripts
+    pub fn infer_arg_types<B: ByteOrder>(
+        &self,
+        scripts: &[impl AsRef<[u8]>],
+    ) -> Result<ArgPatch, BBScriptError> {
+        let InstructionInfo::Sized(id_map) = &self.instructions else {
+            return Err(BBScriptError::UnsizedConfig);
+        };
+
+        // the data of every occurrence of each instruction with unknown args
+        let mut occurrences: HashMap<u32, Vec<&[u8]>> = HashMap::new();
+        for script in scripts {
+            let script = script.as_ref();
+            let program = self.read_jump_table::<B>(script).and_then(|(_, base)| {
+                Ok((
+                    base,
+                    self.parse_located_with::<B>(script, ParseRecovery::RawTail)?,
+                ))
+            });
+
+            let (base, program) = match program {
+                Ok(program) => program,
+                Err(e) => {
+                    log::warn!("skipping script that can't be parsed: {e}");
+                    continue;
+                }
+            };
+
+            for located in program {
+                let id = match &located.instruction.identifier {
+                    InstructionIdentifier::Id(id) => *id,
+                    InstructionIdentifier::RawData => continue,
+                    identifier => match self.get_by_identifier(identifier) {
+                        Ok(info) => info.id(),
+                        Err(_) => continue,
+                    },
+                };
+
+                let data =
+                    unknown_arg_data(id_map, id, script, base + located.offset, located.size);
+                if let Some(data) = data {
+                    occurrences.entry(id).or_default().push(data);
+                }
+            }
+        }
+
+        let mut patch = ArgPatch::default();
+        for (id, data) in occurrences {
+            let instruction = &id_map[&id];
+            let mut proposal = ArgProposal {
+                name: instruction.name.clone(),
+                occurrences: data.len(),
+                args: Vec::new(),
+                enums: BTreeMap::new(),
+            };
+
+            let mut offset = 0;
+            for (index, arg) in instruction.args().into_iter().enumerate() {
+                let ArgType::Unknown(size) = arg else {
+                    offset += arg.size();
+                    proposal.args.push(arg);
+                    continue;
+                };
+
+                let blobs: Vec<&[u8]> = data.iter().map(|d| &d[offset..offset + size]).collect();
+                let enum_name = |position: usize| {
+                    let name = if instruction.name.is_empty() {
+                        format!("UNKNOWN{id}")
+                    } else {
+                        instruction.name.clone()
+                    };
+                    format!("{name}_{index}_{position}_{id}")
+                };
+
+                self.propose_args::<B>(&blobs, enum_name, &mut proposal);
+                offset += size;
+            }
+
+            patch.instructions.insert(id, proposal);
+        }
+
+        Ok(patch)
+    }
+
+    /// Splits unknown data into typed args, using the same data from every occurrence of an instruction
+    fn propose_args<B: ByteOrder>(
+        &self,
+        blobs: &[&[u8]],
+        enum_name: impl Fn(usize) -> String,
+        proposal: &mut ArgProposal,
+    ) {
+        let size = blobs.first().map_or(0, |b| b.len());
+        let mut offset = 0;
+        let mut position = 0;
+
+        while offset < size {
+            let field = |len: usize| -> Option<Vec<&[u8]>> {
+                (offset + len <= size)
+                    .then(|| blobs.iter().map(|b| &b[offset..offset + len]).collect())
+            };
+
+            let arg = if field(ArgType::STRING32_SIZE).is_some_and(|f| is_string_field(&f)) {
+                ArgType::String32
+            } else if field(ArgType::STRING16_SIZE).is_some_and(|f| is_string_field(&f)) {
+                ArgType::String16
+            } else if field(ArgType::AccessedValue.size())
+                .is_some_and(|f| self.is_tagged_field::<B>(&f))
+            {
+                ArgType::AccessedValue
+            } else if let Some(field) = field(ArgType::Number.size()) {
+                let values: BTreeSet<BBSNumber> = field.iter().map(|b| B::read_i32(b)).collect();
+
+                if (2..=MAX_ENUM_VALUES).contains(&values.len())
+                    && field.len() >= values.len() * MIN_ENUM_OCCURRENCES_PER_VALUE
+                {
+                    let name = enum_name(position);
+                    proposal.enums.insert(name.clone(), values);
+                    ArgType::Enum(name)
+                } else {
+                    ArgType::Number
+                }
+            } else {
+                ArgType::Unknown(size - offset)
+            };
+
+            offset += arg.size();
+            position += 1;
+            proposal.args.push(arg);
+        }
+    }
+
+    /// Every occurrence has a literal or variable tag, and at least one is a variable
+    fn is_tagged_field<B: ByteOrder>(&self, field: &[&[u8]]) -> bool {
+        let tags = field.iter().map(|b| B::read_i32(b));
+        let mut tags_valid = true;
+        let mut has_variable = false;
+
+        for tag in tags {
+            has_variable |= tag == self.variable_tag;
+            tags_valid &= tag == self.variable_tag || tag == self.literal_tag;
+        }
+
+        tags_valid && has_variable
+    }
+}
+
+/// Data of an instruction's args, if its config args contain unknown data
+fn unknown_arg_data<'a>(
+    id_map: &HashMap<u32, SizedInstruction>,
+    id: u32,
+    script: &'a [u8],
+    offset: usize,
+    size: usize,
+) -> Option<&'a [u8]> {
+    let instruction = id_map.get(&id)?;
+    if size != instruction.size
+        || !instruction
+            .args()
+            .iter()
+            .any(|arg| matches!(arg, ArgType::Unknown(_)))
+    {
+        return None;
+    }
+
+    script.get(offset + ALIGNMENT..offset + size)
+}
+
+/// Every occurrence is printable text padded with zeros or filling the whole field,
+/// and at least one is too long to be a number
+fn is_string_field(field: &[&[u8]]) -> bool {
+    let mut longest = 0;
+
+    for bytes in field {
+        let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        let (text, padding) = bytes.split_at(len);
+
+        if padding.iter().any(|b| *b != 0) || text.iter().any(|b| !(0x20..0x7F).contains(b)) {
+            return false;
+        }
+
+        longest = longest.max(len);
+    }
+
+    longest >= MIN_STRING_LENGTH
+}
+
+/// A slice of script data between two jump table entries, which should decode exactly
+struct Segment<'a> {
+    bytes: &'a [u8],
+}
+
+enum Walk {
+    /// The segment decodes exactly, using these instruction IDs
+    Decoded(Vec<u32>),
+    /// The segment did not decode, but these IDs were read in order before it failed
+    Failed(Vec<u32>),
+}
+
+impl ScriptConfig {
+    /// Infers the sizes of unknown or changed instructions in a sized config from a set of scripts.
+    ///
+    /// The jump table of each script splits it into states that have to decode exactly with balanced blocks,
+    /// so the sizes that let the most states decode are picked one instruction at a time
+    pub fn infer_sizes<B: ByteOrder>(
+        &self,
+        scripts: &[impl AsRef<[u8]>],
+    ) -> Result<SizeInference, BBScriptError> {
+        let InstructionInfo::Sized(id_map) = &self.instructions else {
+            return Err(BBScriptError::UnsizedConfig);
+        };
+
+        let mut segments = Vec::new();
+        for script in scripts {
+            let script = script.as_ref();
+            let (jump_table, base) = match self.read_jump_table::<B>(script) {
+                Ok(table) => table,
+                Err(e) => {
+                    log::warn!("skipping script with unreadable jump table: {e}");
+                    continue;
+                }
+            };
+
+            let data = &script[base..];
+            let mut anchors: Vec<usize> = jump_table
+                .iter()
+                .map(|e| e.offset as usize)
+                .filter(|offset| *offset < data.len())
+                .chain([0, data.len()])
+                .collect();
+            anchors.sort_unstable();
+            anchors.dedup();
+
+            segments.extend(anchors.windows(2).map(|w| Segment {
+                bytes: &data[w[0]..w[1]],
+            }));
+        }
+
+        let max_id = id_map.keys().copied().max().unwrap_or(0);
+        let mut sizes: HashMap<u32, usize> = id_map.iter().map(|(id, i)| (*id, i.size)).collect();
+
+        // IDs whose config size is confirmed by a state decoding exactly
+        let mut verified = HashSet::new();
+        let mut failed_walks = Vec::new();
+        for segment in &segments {
+            match self.walk::<B>(segment, &sizes) {
+                Walk::Decoded(ids) => verified.extend(ids),
+                Walk::Failed(ids) => failed_walks.push(ids),
+            }
+        }
+
+        // the first unconfirmed instruction in a state that fails to decode might have the wrong size,
+        // anything after it could just be misaligned data
+        let mut unsolved: HashSet<u32> = failed_walks
+            .iter()
+            .filter_map(|ids| ids.iter().find(|id| !verified.contains(*id)).copied())
+            .collect();
+        unsolved.extend(
+            failed_walks
+                .iter()
+                .filter_map(|ids| ids.last())
+                .filter(|id| !sizes.contains_key(*id)),
+        );
+        for id in &unsolved {
+            sizes.remove(id);
+        }
+
+        let mut result = SizeInference {
+            total_states: segments.len(),
+            ..Default::default()
+        };
+
+        // states that don't decode yet are searched for every combination of sizes that decodes them,
+        // then the size with the most support is fixed and the search is repeated with it
+        loop {
+            // per ID and size: states where every solution uses it, and the share of solutions using it
+            let mut support: HashMap<(u32, usize), (usize, f32)> = HashMap::new();
+
+            for segment in &segments {
+                if matches!(self.walk::<B>(segment, &sizes), Walk::Decoded(_)) {
+                    continue;
+                }
+
+                let mut search = Search {
+                    config: self,
+                    id_map,
+                    sizes: &sizes,
+                    max_id,
+                    bytes: segment.bytes,
+                    assigned: Vec::new(),
+                    solutions: Vec::new(),
+                    budget: SEARCH_BUDGET,
+                };
+                search.run::<B>(0, 0);
+
+                let solutions = search.solutions;
+                if solutions.is_empty() || solutions.len() > MAX_SOLUTIONS || search.budget == 0 {
+                    continue;
+                }
+
+                let mut counts: HashMap<(u32, usize), usize> = HashMap::new();
+                for assignment in solutions.iter().flatten() {
+                    *counts.entry(*assignment).or_default() += 1;
+                }
+
+                for (assignment, count) in counts {
+                    let entry = support.entry(assignment).or_default();
+                    if count == solutions.len() {
+                        entry.0 += 1;
+                    }
+                    entry.1 += count as f32 / solutions.len() as f32;
+                }
+            }
+
+            let best = support
+                .into_iter()
+                .max_by(|(a, a_support), (b, b_support)| {
+                    a_support
+                        .0
+                        .cmp(&b_support.0)
+                        .then(a_support.1.total_cmp(&b_support.1))
+                        .then(b.cmp(a))
+                });
+
+            let Some(((id, size), _)) = best else {
+                break;
+            };
+
+            sizes.insert(id, size);
+            unsolved.remove(&id);
+            result.sizes.insert(
+                id,
+                InferredSize {
+                    size: Some(size),
+                    previous_size: id_map.get(&id).map(|i| i.size),
+                    votes: 0,
+                    conflicts: 0,
+                    confidence: 0.0,
+                },
+            );
+        }
+
+        // every state using an inferred size counts for it if it decodes with the final sizes, and against it otherwise
+        for segment in &segments {
+            let (ids, decoded) = match self.walk::<B>(segment, &sizes) {
+                Walk::Decoded(ids) => (ids, true),
+                Walk::Failed(ids) => (ids, false),
+            };
+
+            let mut ids = ids;
+            ids.sort_unstable();
+            ids.dedup();
+
+            for id in ids {
+                if let Some(inferred) = result.sizes.get_mut(&id) {
+                    if decoded {
+                        inferred.votes += 1;
+                    } else {
+                        inferred.conflicts += 1;
+                    }
+                }
+            }
+
+            result.decoded_states += decoded as usize;
+        }
+
+        for inferred in result.sizes.values_mut() {
+            if inferred.votes > 0 {
+                inferred.confidence =
+                    inferred.votes as f32 / (inferred.votes + inferred.conflicts) as f32;
+            }
+        }
+
+        for id in unsolved {
+            result.sizes.entry(id).or_insert(InferredSize {
+                size: None,
+                previous_size: id_map.get(&id).map(|i| i.size),
+                votes: 0,
+                conflicts: 0,
+                confidence: 0.0,
+            });
+        }
+
+        // sizes that were confirmed as they already are don't need to be reported
+        result.sizes.retain(|_, inferred| {
+            inferred.size.is_none() || inferred.size != inferred.previous_size
+        });
+
+        Ok(result)
+    }
+
+    /// Decodes a segment using only known sizes
+    fn walk<B: ByteOrder>(&self, segment: &Segment, sizes: &HashMap<u32, usize>) -> Walk {
+        let mut ids = Vec::new();
+        let mut position = 0;
+
+        while position < segment.bytes.len() {
+            let Some(id) = read_id::<B>(segment.bytes, position) else {
+                return Walk::Failed(ids);
+            };
+            ids.push(id);
+
+            if position != 0 && self.is_jump_entry_id(id) {
+                return Walk::Failed(ids);
+            }
+
+            match sizes.get(&id) {
+                Some(size) if *size >= ALIGNMENT => position += size,
+                _ => return Walk::Failed(ids),
+            }
+        }
+
+        if position == segment.bytes.len() {
+            Walk::Decoded(ids)
+        } else {
+            Walk::Failed(ids)
+        }
+    }
+}
+
+/// Depth first search for sizes of unknown instructions that decode a segment exactly
+struct Search<'a> {
+    config: &'a ScriptConfig,
+    id_map: &'a HashMap<u32, SizedInstruction>,
+    sizes: &'a HashMap<u32, usize>,
+    /// Highest instruction ID in the config
+    max_id: u32,
+    bytes: &'a [u8],
+    /// Sizes assumed for unknown instructions along the current path
+    assigned: Vec<(u32, usize)>,
+    solutions: Vec<Vec<(u32, usize)>>,
+    budget: usize,
+}
+
+impl Search<'_> {
+    /// `depth` is the block depth at `position`, every block opened in a segment has to be closed by its end
+    fn run<B: ByteOrder>(&mut self, mut position: usize, mut depth: usize) {
+        loop {
+            if self.budget == 0 || self.solutions.len() > MAX_SOLUTIONS {
+                return;
+            }
+            self.budget -= 1;
+
+            if position == self.bytes.len() {
+                if depth == 0 {
+                    self.solutions.push(self.assigned.clone());
+                }
+                return;
+            }
+
+            let Some(id) = read_id::<B>(self.bytes, position) else {
+                return;
+            };
+
+            // jump table instructions only appear at the start of a segment
+            if position != 0 && self.config.is_jump_entry_id(id) {
+                return;
+            }
+
+            // instructions missing from the config are assumed not to change the block depth
+            match self.id_map.get(&id).map(|i| &i.code_block) {
+                Some(CodeBlock::Begin) => depth += 1,
+                Some(CodeBlock::End) if depth > 1 => depth -= 1,
+                Some(CodeBlock::EndState) if depth > 0 => depth = 0,
+                Some(CodeBlock::End | CodeBlock::EndState) => return,
+                _ => {}
+            }
+
+            let known = self
+                .sizes
+                .get(&id)
+                .or_else(|| self.assigned.iter().find(|(i, _)| *i == id).map(|(_, s)| s));
+
+            match known {
+                Some(size) if *size >= ALIGNMENT => position += size,
+                Some(_) => return,
+                None => break,
+            }
+        }
+
+        // new instructions get IDs close to the known ones, anything far outside is misaligned data
+        let id = read_id::<B>(self.bytes, position).unwrap();
+        if !self.id_map.contains_key(&id) && id > self.max_id.saturating_add(MAX_NEW_ID_DISTANCE) {
+            return;
+        }
+
+        // try every size the unknown instruction could have
+        let remaining = self.bytes.len() - position;
+
+        for size in (ALIGNMENT..=remaining.min(MAX_INFERRED_SIZE)).step_by(ALIGNMENT) {
+            self.assigned.push((id, size));
+            self.run::<B>(position + size, depth);
+            self.assigned.pop();
+
+            if self.budget == 0 || self.solutions.len() > MAX_SOLUTIONS {
+                return;
+            }
+        }
+    }
+}
+
+fn read_id<B: ByteOrder>(bytes: &[u8], position: usize) -> Option<u32> {
+    bytes.get(position..position + 4).map(B::read_u32)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::bytes;
+    use crate::game_config::{ArgType, InstructionInfo};
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  ifOperation: (IS_GREATER), Mem(Tmp), Val(2)
+    exitState:
+  endIf:
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+  exitState:
+endState:
+beginState: s32'CmnActJump'
+  ifOperation: (IS_LESSER), Mem(Tmp), Val(8)
+    callSubroutine: s32'cmnJump'
+  endIf:
+endState:
+";
+
+    #[test]
+    fn infer_changed_and_unknown_sizes() {
+        let bytes = bytes(SCRIPT);
+
+        // pretend `exitState` is a new instruction missing from the config,
+        // and `sprite` grew by 4 bytes in a new version of the game
+        let mut config = SupportedGame::Ggst.into_config();
+        if let crate::game_config::InstructionInfo::Sized(map) = &mut config.instructions {
+            map.remove(&18);
+            map.get_mut(&2).unwrap().size = 36;
+        }
+
+        let inference = config.infer_sizes::<LittleEndian>(&[&bytes]).unwrap();
+
+        let exit_state = &inference.sizes[&18];
+        assert_eq!(exit_state.size, Some(4));
+        assert_eq!(exit_state.previous_size, None);
+
+        let sprite = &inference.sizes[&2];
+        assert_eq!(sprite.size, Some(40));
+        assert_eq!(sprite.previous_size, Some(36));
+        assert_eq!(sprite.confidence, 1.0);
+
+        assert_eq!(inference.decoded_states, inference.total_states);
+
+        inference.apply(&mut config).unwrap();
+        assert_eq!(
+            config.parse::<LittleEndian>(&bytes).unwrap().len(),
+            SupportedGame::Ggst
+                .into_config()
+                .parse::<LittleEndian>(&bytes)
+                .unwrap()
+                .len()
+        );
+
+        // the scripts are unchanged, so nothing should be reported for the original config
+        let inference = SupportedGame::Ggst
+            .into_config()
+            .infer_sizes::<LittleEndian>(&[&bytes])
+            .unwrap();
+        assert!(inference.sizes.is_empty());
+    }
+
+    #[test]
+    fn infer_unknown_arg_types() {
+        let mut script = String::new();
+        for i in 0..20 {
+            let operation = if i % 2 == 0 {
+                "IS_GREATER"
+            } else {
+                "IS_LESSER"
+            };
+            let value = if i % 3 == 0 {
+                "Mem(PosX)".into()
+            } else {
+                format!("Val({i})")
+            };
+
+            // one sprite name fills its whole field, without a terminator
+            let sprite = if i == 0 {
+                "nmc000_00_".repeat(4)[..32].to_string()
+            } else {
+                format!("nmc{i:03}_00")
+            };
+
+            script.push_str(&format!(
+                "beginState: s32'CmnAct{i}'\n\
+                 sprite: s32'{sprite}', {}\n\
+                 ifOperation: ({operation}), Mem(Tmp), {value}\n\
+                 endIf:\n\
+                 endState:\n",
+                i * 7
+            ));
+        }
+
+        let bytes = bytes(&script);
+
+        // forget the args of `sprite` and `ifOperation`, leaving only unknown data
+        let mut config = SupportedGame::Ggst.into_config();
+        if let InstructionInfo::Sized(map) = &mut config.instructions {
+            map.get_mut(&2).unwrap().set_args(&[]);
+            map.get_mut(&6).unwrap().set_args(&[]);
+        }
+
+        let patch = config.infer_arg_types::<LittleEndian>(&[&bytes]).unwrap();
+
+        let sprite = &patch.instructions[&2];
+        assert_eq!(sprite.occurrences, 20);
+        assert_eq!(sprite.args, [ArgType::String32, ArgType::Number]);
+
+        let if_operation = &patch.instructions[&6];
+        assert_eq!(
+            if_operation.args,
+            [
+                ArgType::Enum("ifOperation_0_0_6".into()),
+                ArgType::AccessedValue,
+                ArgType::AccessedValue
+            ]
+        );
+        assert_eq!(if_operation.enums["ifOperation_0_0_6"].len(), 2);
+
+        // instructions with fully typed args aren't part of the patch
+        assert_eq!(patch.instructions.len(), 2);
+
+        patch.apply(&mut config).unwrap();
+        assert!(config.named_value_maps.contains_key("ifOperation_0_0_6"));
+        assert!(config.parse_to_string::<LittleEndian>(&bytes, 12).is_ok());
+    }
+}
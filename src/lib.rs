@@ -0,0 +1,187 @@
+//! Parsing and rebuilding of BBScript, the script format used by Arc System Works games.
+//!
+//! Scripts are decoded with a [`ScriptConfig`], either loaded from a RON file with
+//! [`ScriptConfig::load`] or taken from one of the configs embedded in the crate via
+//! [`SupportedGame::into_config`].
+//!
+//! ```no_run
+//! use bbscript::{rebuild_bbscript, SupportedGame};
+//! use bbscript::byteorder::LittleEndian;
+//!
+//! # fn main() -> Result<(), bbscript::BBScriptError> {
+//! let script = std::fs::read("ggst_script.bbsbin")?;
+//! let config = SupportedGame::Ggst.into_config();
+//!
+//! let readable = config.parse_to_string::<LittleEndian>(&script, 12)?;
+//! let rebuilt = rebuild_bbscript::<LittleEndian>(config, readable)?;
+//!
+//! assert_eq!(script, rebuilt);
+//! # Ok(())
+//! # }
+//! ```
+
+pub(crate) mod aliases;
+pub(crate) mod ast;
+pub(crate) mod container;
+pub(crate) mod detect;
+pub(crate) mod diff;
+pub(crate) mod error;
+#[cfg(test)]
+mod fixture;
+pub(crate) mod game_config;
+pub(crate) mod header;
+pub(crate) mod inference;
+pub(crate) mod merge;
+pub(crate) mod pac;
+pub(crate) mod parser;
+pub(crate) mod patch;
+pub(crate) mod preprocess;
+pub(crate) mod rebuilder;
+pub(crate) mod states;
+pub(crate) mod uasset;
+pub(crate) mod verify;
+
+pub use crate::aliases::ScriptAliases;
+pub use crate::ast::{Node, NodeId, NodeKind, Script};
+pub use crate::container::{Container, ContainerKind, LengthField};
+pub use crate::detect::{detect_game, Candidate, Detection};
+pub use crate::diff::{
+    diff_scripts, ArgChange, InstructionChange, NamedNode, NodeDiff, ScriptDiff,
+};
+pub use crate::error::BBScriptError;
+pub use crate::game_config::{
+    ArgType, BBSNumber, CodeBlock, ExpressionSyntax, GenericInstruction, Instruction,
+    JumpTableOptions, JumpTableOrder, ScriptConfig, SizedInstruction, SizedString, TaggedValue,
+    UnsizedInstruction,
+};
+pub use crate::header::{BlockStyle, ScriptHeader};
+pub use crate::inference::{ArgPatch, ArgProposal, InferredSize, SizeInference};
+pub use crate::merge::{merge_scripts, Merge};
+pub use crate::pac::{Pac, PacEntry};
+pub use crate::parser::{
+    ArgValue, InstructionIdentifier, InstructionValue, JumpEntry, JumpTableIssue, JumpTableProblem,
+    LocatedInstruction, ParseRecovery, StructuredScript,
+};
+pub use crate::patch::{StateChange, StatePatch};
+pub use crate::rebuilder::{rebuild_bbscript, rebuild_bbscript_at, rebuild_instructions};
+pub use crate::states::StateInfo;
+pub use crate::uasset::UassetPair;
+pub use crate::verify::Mismatch;
+
+// the parse and rebuild entry points are generic over `ByteOrder`
+pub use byteorder;
+
+use byteorder::ByteOrder;
+use serde::{Deserialize, Serialize};
+
+pub(crate) type HashMap<K, V> = std::collections::HashMap<K, V>;
+
+/// 64-bit FNV-1a hash, used where hashes are saved to files and have to stay the same across versions
+pub(crate) fn fnv1a_hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+    const PRIME: u64 = 0x100000001B3;
+
+    bytes.into_iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+pub const BBCF_CONFIG: &str = include_str!("../static_db/bbcf.ron");
+pub const DBFZ_CONFIG: &str = include_str!("../static_db/dbfz.ron");
+pub const DNF_CONFIG: &str = include_str!("../static_db/dnf.ron");
+pub const GBVS_CONFIG: &str = include_str!("../static_db/gbvs.ron");
+pub const GBVSR_CONFIG: &str = include_str!("../static_db/gbvsr.ron");
+pub const GGREV2_CONFIG: &str = include_str!("../static_db/ggrev2.ron");
+pub const GGST_CONFIG: &str = include_str!("../static_db/ggst.ron");
+pub const P4U2_CONFIG: &str = include_str!("../static_db/p4u2.ron");
+
+/// A game with a config embedded in the crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SupportedGame {
+    /// Blazblue: Centralfiction
+    Bbcf,
+    /// Dragon Ball FighterZ
+    Dbfz,
+    /// DNF Duel
+    Dnf,
+    /// Granblue Fantasy Versus
+    Gbvs,
+    /// Granblue Fantasy Versus: Rising
+    Gbvsr,
+    /// Guilty Gear Xrd Rev2
+    Ggrev2,
+    /// Guilty Gear Strive
+    Ggst,
+    /// Persona 4 Arena Ultimax
+    P4u2,
+}
+
+impl SupportedGame {
+    /// Every game with an embedded config
+    pub const ALL: [SupportedGame; 8] = [
+        SupportedGame::Bbcf,
+        SupportedGame::Dbfz,
+        SupportedGame::Dnf,
+        SupportedGame::Gbvs,
+        SupportedGame::Gbvsr,
+        SupportedGame::Ggrev2,
+        SupportedGame::Ggst,
+        SupportedGame::P4u2,
+    ];
+
+    /// The embedded RON source of this game's config
+    pub const fn config_source(self) -> &'static str {
+        match self {
+            SupportedGame::Bbcf => BBCF_CONFIG,
+            SupportedGame::Dbfz => DBFZ_CONFIG,
+            SupportedGame::Dnf => DNF_CONFIG,
+            SupportedGame::Gbvs => GBVS_CONFIG,
+            SupportedGame::Gbvsr => GBVSR_CONFIG,
+            SupportedGame::Ggrev2 => GGREV2_CONFIG,
+            SupportedGame::Ggst => GGST_CONFIG,
+            SupportedGame::P4u2 => P4U2_CONFIG,
+        }
+    }
+
+    pub fn into_config(self) -> ScriptConfig {
+        let result = ScriptConfig::new(self.config_source().as_bytes());
+
+        // all embedded configs should parse correctly so this should be infallible
+        let mut config = result.unwrap();
+        config.game = Some(self);
+
+        config
+    }
+}
+
+/// Byte order of the numbers in a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Endianness {
+    Little,
+    /// Used by PS3 games
+    Big,
+}
+
+impl Endianness {
+    /// The endianness of a [`ByteOrder`] type
+    pub fn of<B: ByteOrder>() -> Self {
+        if B::read_u16(&[0, 1]) == 1 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SupportedGame;
+
+    #[test]
+    fn embedded_configs() {
+        for game in SupportedGame::ALL {
+            let _ = game.into_config();
+        }
+    }
+}
@@ -1,72 +1,17 @@
-mod error;
-mod game_config;
-mod parser;
-mod rebuilder;
-
 use anyhow::{anyhow, Result as AResult};
+use bbscript::{
+    detect_game, diff_scripts, merge_scripts, rebuild_bbscript_at, rebuild_instructions, ArgPatch,
+    BBScriptError, BlockStyle, Container, ContainerKind, Endianness, InstructionChange,
+    InstructionValue, JumpTableIssue, LengthField, Pac, ParseRecovery, ScriptAliases, ScriptConfig,
+    ScriptHeader, StatePatch, StructuredScript, SupportedGame, UassetPair,
+};
+use clap::builder::PossibleValue;
 use clap::{crate_version, Args, Parser, Subcommand, ValueEnum};
-use game_config::ScriptConfig;
-
-extern crate pest_derive;
 
 use std::fs::{metadata, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
-use crate::error::BBScriptError;
-#[cfg(feature = "old-cfg-converter")]
-use crate::game_config::GameDB;
-use crate::rebuilder::rebuild_bbscript;
-
-type HashMap<K, V> = std::collections::HashMap<K, V>;
-
-const BBCF_CONFIG: &str = include_str!("../static_db/bbcf.ron");
-const DBFZ_CONFIG: &str = include_str!("../static_db/dbfz.ron");
-const DNF_CONFIG: &str = include_str!("../static_db/dnf.ron");
-const GBVS_CONFIG: &str = include_str!("../static_db/gbvs.ron");
-const GBVSR_CONFIG: &str = include_str!("../static_db/gbvsr.ron");
-const GGREV2_CONFIG: &str = include_str!("../static_db/ggrev2.ron");
-const GGST_CONFIG: &str = include_str!("../static_db/ggst.ron");
-const P4U2_CONFIG: &str = include_str!("../static_db/p4u2.ron");
-
-#[derive(Debug, Clone, Copy, ValueEnum)]
-enum SupportedGame {
-    /// Blazblue: Centralfiction
-    Bbcf,
-    /// Dragon Ball FighterZ
-    Dbfz,
-    /// DNF Duel
-    Dnf,
-    /// Granblue Fantasy Versus
-    Gbvs,
-    /// Granblue Fantasy Versus: Rising
-    Gbvsr,
-    /// Guilty Gear Xrd Rev2
-    Ggrev2,
-    /// Guilty Gear Strive
-    Ggst,
-    /// Persona 4 Arena Ultimax
-    P4u2,
-}
-
-impl SupportedGame {
-    pub fn into_config(self) -> ScriptConfig {
-        let result = match self {
-            SupportedGame::Bbcf => ScriptConfig::new(BBCF_CONFIG.as_bytes()),
-            SupportedGame::Dbfz => ScriptConfig::new(DBFZ_CONFIG.as_bytes()),
-            SupportedGame::Dnf => ScriptConfig::new(DNF_CONFIG.as_bytes()),
-            SupportedGame::Gbvs => ScriptConfig::new(GBVS_CONFIG.as_bytes()),
-            SupportedGame::Gbvsr => ScriptConfig::new(GBVSR_CONFIG.as_bytes()),
-            SupportedGame::Ggrev2 => ScriptConfig::new(GGREV2_CONFIG.as_bytes()),
-            SupportedGame::Ggst => ScriptConfig::new(GGST_CONFIG.as_bytes()),
-            SupportedGame::P4u2 => ScriptConfig::new(P4U2_CONFIG.as_bytes()),
-        };
-
-        // all embedded configs should parse correctly so this should be infallible
-        result.unwrap()
-    }
-}
-
 fn main() {
     if let Err(e) = run() {
         println!("ERROR: {}", e);
@@ -92,14 +37,69 @@ struct MainCli {
 #[derive(Args, Debug, Clone)]
 #[group(required = true, multiple = false)]
 struct ConfigArgs {
-    /// A game supported by BBScript internally
+    /// A game supported by BBScript internally, or `auto` to detect it along with the byte order
     #[arg(short, long, group = "game-config")]
-    game: Option<SupportedGame>,
+    game: Option<GameSelection>,
     /// A custom config file stored externally
     #[arg(short, long, group = "game-config")]
     config_file: Option<PathBuf>,
 }
 
+/// Same as [`ConfigArgs`], for commands that can also take the config from the header of a readable script
+#[derive(Args, Debug, Clone)]
+#[group(required = false, multiple = false)]
+struct HeaderConfigArgs {
+    /// A game supported by BBScript internally, overrides the game in the script's header
+    #[arg(short, long, group = "game-config")]
+    game: Option<GameSelection>,
+    /// A custom config file stored externally, overrides the game in the script's header
+    #[arg(short, long, group = "game-config")]
+    config_file: Option<PathBuf>,
+}
+
+impl HeaderConfigArgs {
+    fn into_config_args(self) -> Option<ConfigArgs> {
+        (self.game.is_some() || self.config_file.is_some()).then_some(ConfigArgs {
+            game: self.game,
+            config_file: self.config_file,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum GameSelection {
+    Auto,
+    Game(SupportedGame),
+}
+
+const GAME_SELECTIONS: [GameSelection; SupportedGame::ALL.len() + 1] = {
+    let mut selections = [GameSelection::Auto; SupportedGame::ALL.len() + 1];
+
+    let mut i = 0;
+    while i < SupportedGame::ALL.len() {
+        selections[i + 1] = GameSelection::Game(SupportedGame::ALL[i]);
+        i += 1;
+    }
+
+    selections
+};
+
+impl ValueEnum for GameSelection {
+    fn value_variants<'a>() -> &'a [Self] {
+        &GAME_SELECTIONS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            GameSelection::Auto => Some(
+                PossibleValue::new("auto")
+                    .help("Detects the game and byte order from the input script"),
+            ),
+            GameSelection::Game(game) => game.to_possible_value(),
+        }
+    }
+}
+
 /// Parses BBScript into an easily moddable format that can be rebuilt into usable BBScript
 #[derive(Subcommand)]
 enum SubCmd {
@@ -123,14 +123,38 @@ enum SubCmd {
         /// Takes a hex offset from the end of the file specifying where the script actually ends
         #[clap(short, long, value_parser(parse_hex))]
         end_offset: Option<usize>,
+        /// A u32 before the script that holds its size, as a hex OFFSET from the start of the file,
+        /// or OFFSET+EXTRA if it also counts EXTRA (in hex) other bytes. It's updated when the script is rebuilt
+        #[arg(long, value_parser(parse_length_field))]
+        length_field: Vec<LengthField>,
         #[arg(short, long, default_value_t = 12)]
         indent_limit: usize,
+        /// Writes blocks wrapped in `{ }` instead of writing their end instructions
+        #[arg(long)]
+        braces: bool,
+        /// A readable file of `#var` and `#const` declarations. Its variables are used as names for `Mem(...)`,
+        /// and the script includes the file so it can be rebuilt
+        #[arg(long)]
+        aliases: Option<PathBuf>,
+        /// How to handle data that can't be decoded with the config
+        #[arg(short, long, value_enum, default_value_t = ParseRecovery::Strict)]
+        recovery: ParseRecovery,
+        /// Reads the script from the file with this name, treating INPUT as an FPAC archive
+        #[arg(long)]
+        pac_entry: Option<String>,
     },
-    /// Rebuilds readable BBScript into BBScript usable by games
+    /// Tries every supported game in both byte orders on a script and reports the best match
+    Detect {
+        /// BBScript file to detect the game of
+        #[clap(name = "INPUT")]
+        input: PathBuf,
+    },
+    /// Rebuilds readable BBScript into BBScript usable by games.
+    /// The game, byte order and container are taken from the script's header unless given
     Rebuild {
         /// File name of a config within the game DB folder
         #[clap(flatten)]
-        game: ConfigArgs,
+        game: HeaderConfigArgs,
         /// Readable script to use as input
         #[arg(name = "INPUT")]
         input: PathBuf,
@@ -140,6 +164,172 @@ enum SubCmd {
         /// Enables overwriting the file if a file with the same name as OUTPUT already exists
         #[arg(short, long)]
         overwrite: bool,
+        /// Writes all numbers in little-endian format, even if the script's header says otherwise
+        #[arg(long, conflicts_with = "big_endian")]
+        little_endian: bool,
+        /// A u32 before the script to update with its size, as a hex OFFSET from the start of the file,
+        /// or OFFSET+EXTRA if it also counts EXTRA (in hex) other bytes. Added to the fields in the script's header
+        #[arg(long, value_parser(parse_length_field))]
+        length_field: Vec<LengthField>,
+        /// Writes a copy of this FPAC archive to OUTPUT, with the rebuilt script stored in it
+        #[arg(long, requires = "pac_entry")]
+        pac: Option<PathBuf>,
+        /// Name of the script's file within the archive given with --pac
+        #[arg(long, requires = "pac")]
+        pac_entry: Option<String>,
+    },
+    /// Parses and rebuilds scripts, checking that the result is identical to the original
+    Verify {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// A script, or a directory that is searched recursively for scripts
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+    },
+    /// Compares the states and subroutines of two scripts, matching them by name
+    Diff {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// The original script
+        #[arg(name = "OLD")]
+        old: PathBuf,
+        /// The changed script
+        #[arg(name = "NEW")]
+        new: PathBuf,
+        /// Also write the differences to this file as JSON
+        #[arg(long)]
+        json: Option<PathBuf>,
+        /// Enables overwriting the JSON file if it already exists
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Merges the changes two scripts made to the same base script into a readable script.
+    /// Each script can be either binary or readable, and conflicts are marked like in git
+    Merge {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// The script both changed scripts are based on
+        #[arg(name = "BASE")]
+        base: PathBuf,
+        #[arg(name = "OURS")]
+        ours: PathBuf,
+        #[arg(name = "THEIRS")]
+        theirs: PathBuf,
+        /// File to write the merged readable script to
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
+        #[arg(short, long, default_value_t = 12)]
+        indent_limit: usize,
+    },
+    /// Writes the states and subroutines a modded script added, replaced or deleted to a patch file,
+    /// which can be applied to later versions of the original script
+    MakePatch {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// The original script, binary or readable
+        #[arg(name = "BASE")]
+        base: PathBuf,
+        /// The modded script, binary or readable
+        #[arg(name = "MODDED")]
+        modded: PathBuf,
+        /// File to write the patch to
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Applies a patch made with `make-patch` to a binary script
+    ApplyPatch {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// The script to patch
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// The patch file
+        #[arg(name = "PATCH")]
+        patch: PathBuf,
+        /// File to write the patched script to
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Lists every state and subroutine in a script with its offset and size
+    ListStates {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+    },
+    /// Writes chosen states and subroutines of a script to a readable file
+    ExtractStates {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// File to write the readable states to
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Names of the states and subroutines to extract
+        #[arg(name = "NAMES", required = true)]
+        names: Vec<String>,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Replaces states and subroutines of a script with the ones in a readable file, adding any that don't exist yet
+    SpliceStates {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// Readable file with the states to put into the script
+        #[arg(name = "FRAGMENT")]
+        fragment: PathBuf,
+        /// File to write the new script to
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Lists the files in an FPAC archive
+    PacList {
+        /// The `.pac` archive
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+    },
+    /// Extracts every file in an FPAC archive to a directory
+    PacExtract {
+        /// The `.pac` archive
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// Directory to write the files to, which is created if it doesn't exist
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting files that already exist in OUTPUT
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Writes a copy of an FPAC archive with files replaced or added from a directory
+    PacRepack {
+        /// The original `.pac` archive
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// Directory holding the files to replace or add, by file name
+        #[arg(name = "FILES")]
+        files: PathBuf,
+        /// The archive to write
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
     },
     /// Parse a script to machine-readable JSON
     ParseJson {
@@ -155,6 +345,109 @@ enum SubCmd {
         /// Enables overwriting the file if a file with the same name as OUTPUT already exists
         #[clap(short, long)]
         overwrite: bool,
+        /// How to handle data that can't be decoded with the config
+        #[arg(short, long, value_enum, default_value_t = ParseRecovery::Strict)]
+        recovery: ParseRecovery,
+    },
+    /// Parses the script inside of a uasset/uexp pair, like the ones used by Guilty Gear Strive
+    Unpack {
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// The uasset file, its uexp is expected next to it with the same name
+        #[clap(name = "UASSET")]
+        input: PathBuf,
+        /// File to write readable script to as output
+        #[clap(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[clap(short, long)]
+        overwrite: bool,
+        #[arg(short, long, default_value_t = 12)]
+        indent_limit: usize,
+        /// How to handle data that can't be decoded with the config
+        #[arg(short, long, value_enum, default_value_t = ParseRecovery::Strict)]
+        recovery: ParseRecovery,
+    },
+    /// Rebuilds a readable script into a copy of the uasset/uexp pair it was unpacked from.
+    /// The game and byte order are taken from the script's header unless given
+    Repack {
+        #[clap(flatten)]
+        game: HeaderConfigArgs,
+        /// Readable script to use as input
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// The original uasset file, its uexp is expected next to it with the same name
+        #[arg(name = "UASSET")]
+        uasset: PathBuf,
+        /// uasset file to write, the uexp is written next to it with the same name
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the files if OUTPUT or its uexp already exist
+        #[arg(short, long)]
+        overwrite: bool,
+        /// Writes all numbers in little-endian format, even if the script's header says otherwise
+        #[arg(long, conflicts_with = "big_endian")]
+        little_endian: bool,
+    },
+    /// Rebuild a script from the JSON output of parse-json
+    RebuildJson {
+        /// File name of a config within the game DB folder
+        #[clap(flatten)]
+        game: ConfigArgs,
+        /// JSON script to use as input
+        #[arg(name = "INPUT")]
+        input: PathBuf,
+        /// File to write rebuilt script to as output
+        #[arg(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[arg(short, long)]
+        overwrite: bool,
+    },
+    /// Infers the sizes of unknown or changed instructions from a directory of scripts and outputs an updated config
+    InferSizes {
+        /// The config to update, has to be a sized config
+        #[clap(name = "CONFIG", flatten)]
+        config: ConfigArgs,
+        /// Directory containing the game's scripts, searched recursively
+        #[clap(name = "INPUT")]
+        input: PathBuf,
+        /// New config file to output with inferred sizes
+        #[clap(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[clap(short, long)]
+        overwrite: bool,
+    },
+    /// Proposes types for unknown instruction args from a directory of scripts, and outputs them as a config patch
+    InferArgs {
+        /// The config with unknown args, has to be a sized config
+        #[clap(name = "CONFIG", flatten)]
+        config: ConfigArgs,
+        /// Directory containing the game's scripts, searched recursively
+        #[clap(name = "INPUT")]
+        input: PathBuf,
+        /// Patch file to output the proposed args to
+        #[clap(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[clap(short, long)]
+        overwrite: bool,
+    },
+    /// Applies a patch from infer-args to a config
+    ApplyArgPatch {
+        /// The patch output by infer-args
+        #[clap(name = "PATCH")]
+        patch: PathBuf,
+        /// The config that will receive the patched args
+        #[clap(name = "CONFIG", flatten)]
+        config: ConfigArgs,
+        /// New config file to output with patched args
+        #[clap(name = "OUTPUT")]
+        output: PathBuf,
+        /// Enables overwriting the file if a file with the same name as OUTPUT already exists
+        #[clap(short, long)]
+        overwrite: bool,
     },
     ConfigSizeUpdate {
         /// The new config that has correct size information
@@ -189,81 +482,528 @@ fn run() -> AResult<()> {
             overwrite,
             start_offset,
             end_offset,
+            length_field,
             indent_limit,
+            braces,
+            aliases,
+            recovery,
+            pac_entry,
         } => {
             confirm_io_files(&input, &output, overwrite)?;
-            let game = get_config(game)?;
+
+            let file = load_file(input)?;
+            let file = match pac_entry {
+                Some(name) => Pac::parse(&file)?
+                    .get(&name)
+                    .ok_or_else(|| anyhow!("The archive has no file named `{name}`"))?
+                    .data
+                    .clone(),
+                None => file,
+            };
+
+            // containers are only detected when the script location isn't given manually
+            let (script, mut container) = match (start_offset, end_offset) {
+                (None, None) => unwrap_container(file),
+                byte_range => {
+                    let (script, container) = slice_script(file, byte_range)?;
+                    (script, Some(container))
+                }
+            };
+
+            let (mut game, big_endian) = resolve_config(game, &script, args.big_endian)?;
+            let aliases = match aliases {
+                Some(path) => {
+                    game.aliases = ScriptAliases::read_at(&std::fs::read_to_string(&path)?, &path)?;
+                    Some(include_path(&path, &output)?)
+                }
+                None => None,
+            };
+            if let Some(container) = container
+                .as_mut()
+                .filter(|c| c.kind == ContainerKind::Sliced)
+            {
+                container.endianness = endianness(big_endian);
+            }
+            add_length_fields(container.as_mut(), length_field)?;
+            let header = ScriptHeader {
+                container,
+                block_style: braces.then_some(BlockStyle::Braces),
+                aliases,
+                ..Default::default()
+            };
             run_parser(
                 game,
-                input,
-                output,
-                (start_offset, end_offset),
-                args.big_endian,
+                script,
+                &output,
+                big_endian,
                 indent_limit,
+                recovery,
+                header,
             )?;
         }
+        SubCmd::Detect { input } => {
+            let (script, _) = unwrap_container(load_file(input)?);
+            run_detection(&script);
+        }
         SubCmd::Rebuild {
             game,
             input,
             output,
             overwrite,
+            little_endian,
+            length_field,
+            pac,
+            pac_entry,
         } => {
             confirm_io_files(&input, &output, overwrite)?;
-            let game = get_config(game)?;
-            run_rebuilder(game, input, output, args.big_endian)?;
+            let (script, header, game, big_endian) =
+                load_readable_script(&input, game, args.big_endian, little_endian)?;
+
+            let mut container = header.container;
+            add_length_fields(container.as_mut(), length_field)?;
+            let pac = pac.zip(pac_entry);
+            run_rebuilder(game, script, &input, output, big_endian, container, pac)?;
         }
-        SubCmd::ParseJson {
+        SubCmd::Verify { game, input } => {
+            run_verify(game, input, args.big_endian)?;
+        }
+        SubCmd::Diff {
+            game,
+            old,
+            new,
+            json,
+            overwrite,
+        } => {
+            if let Some(json) = &json {
+                confirm_io_files(&old, json, overwrite)?;
+            }
+            if !new.is_file() {
+                return Err(BBScriptError::BadInputFile(new.to_string_lossy().into()).into());
+            }
+            let (old, _) = unwrap_container(load_file(old)?);
+            let (new, _) = unwrap_container(load_file(new)?);
+            let (game, big_endian) = resolve_config(game, &old, args.big_endian)?;
+            run_diff(game, &old, &new, big_endian, json)?;
+        }
+        SubCmd::Merge {
+            game,
+            base,
+            ours,
+            theirs,
+            output,
+            overwrite,
+            indent_limit,
+        } => {
+            confirm_io_files(&base, &output, overwrite)?;
+            for input in [&ours, &theirs] {
+                if !input.is_file() {
+                    return Err(BBScriptError::BadInputFile(input.to_string_lossy().into()).into());
+                }
+            }
+            let scripts =
+                [base, ours, theirs].map(|path| load_file(path).map(|f| unwrap_container(f).0));
+            let [base, ours, theirs] = scripts;
+            let (base, ours, theirs) = (base?, ours?, theirs?);
+            let (game, big_endian) = resolve_config(game, &base, args.big_endian)?;
+            run_merge(
+                game,
+                [&base, &ours, &theirs],
+                &output,
+                big_endian,
+                indent_limit,
+            )?;
+        }
+        SubCmd::MakePatch {
+            game,
+            base,
+            modded,
+            output,
+            overwrite,
+        } => {
+            confirm_io_files(&base, &output, overwrite)?;
+            if !modded.is_file() {
+                return Err(BBScriptError::BadInputFile(modded.to_string_lossy().into()).into());
+            }
+            let (base, _) = unwrap_container(load_file(base)?);
+            let (modded, _) = unwrap_container(load_file(modded)?);
+            let (game, big_endian) = resolve_config(game, &base, args.big_endian)?;
+            make_patch(game, &base, &modded, &output, big_endian)?;
+        }
+        SubCmd::ApplyPatch {
             game,
             input,
+            patch,
             output,
             overwrite,
         } => {
             confirm_io_files(&input, &output, overwrite)?;
-            let game = get_config(game)?;
-            run_structured_parser(game, input, output, args.big_endian)?;
+            if !patch.is_file() {
+                return Err(BBScriptError::BadInputFile(patch.to_string_lossy().into()).into());
+            }
+            let (script, container) = unwrap_container(load_file(input)?);
+            let (game, big_endian) = resolve_config(game, &script, args.big_endian)?;
+            let patch = StatePatch::parse(&String::from_utf8_lossy(&load_file(patch)?))?;
+            apply_patch(game, &script, &patch, container, &output, big_endian)?;
         }
-        SubCmd::ConfigSizeUpdate {
-            new_sizes,
-            old_config,
+        SubCmd::ListStates { game, input } => {
+            if !input.is_file() {
+                return Err(BBScriptError::BadInputFile(input.to_string_lossy().into()).into());
+            }
+            let (script, _) = unwrap_container(load_file(input)?);
+            let (game, big_endian) = resolve_config(game, &script, args.big_endian)?;
+            let states = if big_endian {
+                game.list_states::<byteorder::BigEndian>(&script)
+            } else {
+                game.list_states::<byteorder::LittleEndian>(&script)
+            }?;
+
+            for state in states {
+                println!(
+                    "{:#010X} {:#08X} {:?} {}",
+                    state.offset, state.size, state.kind, state.name
+                );
+            }
+        }
+        SubCmd::ExtractStates {
+            game,
+            input,
             output,
+            names,
             overwrite,
         } => {
-            confirm_io_files(&new_sizes, &output, overwrite)?;
-            let new_sizes = ScriptConfig::load(new_sizes)?;
-            let config = get_config(old_config)?;
+            confirm_io_files(&input, &output, overwrite)?;
+            let (script, _) = unwrap_container(load_file(input)?);
+            let (game, big_endian) = resolve_config(game, &script, args.big_endian)?;
+            let text = if big_endian {
+                game.extract_states::<byteorder::BigEndian>(&script, &names)
+            } else {
+                game.extract_states::<byteorder::LittleEndian>(&script, &names)
+            }?;
 
-            update_sizes(new_sizes, config, output)?;
+            File::create(output)?.write_all(text.as_bytes())?;
         }
-    }
-    Ok(())
-}
+        SubCmd::SpliceStates {
+            game,
+            input,
+            fragment,
+            output,
+            overwrite,
+        } => {
+            confirm_io_files(&input, &output, overwrite)?;
+            if !fragment.is_file() {
+                return Err(BBScriptError::BadInputFile(fragment.to_string_lossy().into()).into());
+            }
+            let (script, container) = unwrap_container(load_file(input)?);
+            let (game, big_endian) = resolve_config(game, &script, args.big_endian)?;
+            let fragment = String::from_utf8_lossy(&load_file(fragment)?).into_owned();
+            let spliced = if big_endian {
+                game.splice_states::<byteorder::BigEndian>(&script, &fragment)
+            } else {
+                game.splice_states::<byteorder::LittleEndian>(&script, &fragment)
+            }?;
 
-fn confirm_io_files(
-    input: &PathBuf,
-    output: &PathBuf,
-    overwrite: bool,
-) -> Result<(), BBScriptError> {
-    if Path::new(input).is_file() {
-        if !Path::new(output).exists() || overwrite {
-            Ok(())
-        } else {
-            Err(BBScriptError::OutputAlreadyExists(
-                output.to_string_lossy().into(),
-            ))
+            let spliced = match container {
+                Some(container) => container.wrap(&spliced),
+                None => spliced,
+            };
+            File::create(output)?.write_all(&spliced)?;
         }
-    } else {
-        Err(BBScriptError::BadInputFile(input.to_string_lossy().into()))
-    }
-}
-
-fn get_config(config_args: ConfigArgs) -> AResult<ScriptConfig> {
-    match (config_args.game, config_args.config_file) {
-        (Some(game), None) => Ok(game.into_config()),
-        (None, Some(path)) => Ok(ScriptConfig::load(path)?),
-        _ => panic!("this should never happen"),
-    }
-}
-
+        SubCmd::PacList { input } => {
+            let pac = Pac::parse(&load_file(input)?)?;
+            for entry in &pac.entries {
+                println!("{:>4} {:#010X} {}", entry.id, entry.data.len(), entry.name);
+            }
+        }
+        SubCmd::PacExtract {
+            input,
+            output,
+            overwrite,
+        } => {
+            if !input.is_file() {
+                return Err(BBScriptError::BadInputFile(input.to_string_lossy().into()).into());
+            }
+            extract_pac(input, output, overwrite)?;
+        }
+        SubCmd::PacRepack {
+            input,
+            files,
+            output,
+            overwrite,
+        } => {
+            confirm_io_files(&input, &output, overwrite)?;
+            repack_pac(input, files, output)?;
+        }
+        SubCmd::Unpack {
+            game,
+            input,
+            output,
+            overwrite,
+            indent_limit,
+            recovery,
+        } => {
+            confirm_io_files(&input, &output, overwrite)?;
+            let pair = load_uasset_pair(&input)?;
+            let (script, _) = pair.script()?;
+
+            let (game, big_endian) = resolve_config(game, script, args.big_endian)?;
+            run_parser(
+                game,
+                script.to_owned(),
+                &output,
+                big_endian,
+                indent_limit,
+                recovery,
+                ScriptHeader::default(),
+            )?;
+        }
+        SubCmd::Repack {
+            game,
+            input,
+            uasset,
+            output,
+            overwrite,
+            little_endian,
+        } => {
+            confirm_io_files(&input, &output, overwrite)?;
+            confirm_io_files(&uasset, &output.with_extension("uexp"), overwrite)?;
+            let (script, _, game, big_endian) =
+                load_readable_script(&input, game, args.big_endian, little_endian)?;
+            let pair = load_uasset_pair(&uasset)?;
+
+            run_repack(game, script, &input, pair, output, big_endian)?;
+        }
+        SubCmd::ParseJson {
+            game,
+            input,
+            output,
+            overwrite,
+            recovery,
+        } => {
+            confirm_io_files(&input, &output, overwrite)?;
+            let script = load_file(input)?;
+            let (game, big_endian) = resolve_config(game, &script, args.big_endian)?;
+            run_structured_parser(game, script, output, big_endian, recovery)?;
+        }
+        SubCmd::RebuildJson {
+            game,
+            input,
+            output,
+            overwrite,
+        } => {
+            confirm_io_files(&input, &output, overwrite)?;
+            let game = get_config(game)?;
+            run_structured_rebuilder(game, input, output, args.big_endian)?;
+        }
+        SubCmd::InferSizes {
+            config,
+            input,
+            output,
+            overwrite,
+        } => {
+            confirm_io_dir(&input, &output, overwrite)?;
+            let config = get_config(config)?;
+
+            infer_sizes(config, input, output, args.big_endian)?;
+        }
+        SubCmd::InferArgs {
+            config,
+            input,
+            output,
+            overwrite,
+        } => {
+            confirm_io_dir(&input, &output, overwrite)?;
+            let config = get_config(config)?;
+
+            infer_args(config, input, output, args.big_endian)?;
+        }
+        SubCmd::ApplyArgPatch {
+            patch,
+            config,
+            output,
+            overwrite,
+        } => {
+            confirm_io_files(&patch, &output, overwrite)?;
+            let config = get_config(config)?;
+
+            apply_arg_patch(patch, config, output)?;
+        }
+        SubCmd::ConfigSizeUpdate {
+            new_sizes,
+            old_config,
+            output,
+            overwrite,
+        } => {
+            confirm_io_files(&new_sizes, &output, overwrite)?;
+            let new_sizes = ScriptConfig::load(new_sizes)?;
+            let config = get_config(old_config)?;
+
+            update_sizes(new_sizes, config, output)?;
+        }
+    }
+    Ok(())
+}
+
+fn confirm_io_files(
+    input: &PathBuf,
+    output: &PathBuf,
+    overwrite: bool,
+) -> Result<(), BBScriptError> {
+    if Path::new(input).is_file() {
+        if !Path::new(output).exists() || overwrite {
+            Ok(())
+        } else {
+            Err(BBScriptError::OutputAlreadyExists(
+                output.to_string_lossy().into(),
+            ))
+        }
+    } else {
+        Err(BBScriptError::BadInputFile(input.to_string_lossy().into()))
+    }
+}
+
+fn confirm_io_dir(input: &Path, output: &Path, overwrite: bool) -> Result<(), BBScriptError> {
+    if !input.is_dir() {
+        Err(BBScriptError::BadInputFile(input.to_string_lossy().into()))
+    } else if output.exists() && !overwrite {
+        Err(BBScriptError::OutputAlreadyExists(
+            output.to_string_lossy().into(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn get_config(config_args: ConfigArgs) -> AResult<ScriptConfig> {
+    match (config_args.game, config_args.config_file) {
+        (Some(GameSelection::Game(game)), None) => Ok(game.into_config()),
+        (Some(GameSelection::Auto), None) => Err(anyhow!(
+            "The game can only be detected when the input is a binary script"
+        )),
+        (None, Some(path)) => Ok(ScriptConfig::load(path)?),
+        _ => panic!("this should never happen"),
+    }
+}
+
+/// Gets the config and whether the script is big-endian, detecting both from the script with `--game auto`
+fn resolve_config(
+    config_args: ConfigArgs,
+    script: &[u8],
+    big_endian: bool,
+) -> AResult<(ScriptConfig, bool)> {
+    let Some(GameSelection::Auto) = config_args.game else {
+        return Ok((get_config(config_args)?, big_endian));
+    };
+
+    let detection = detect_game(script);
+    let best = detection
+        .best()
+        .ok_or_else(|| anyhow!("The script doesn't decode with any supported game"))?;
+
+    println!(
+        "Detected {:?} ({:?} endian) with {:.0}% confidence",
+        best.game,
+        best.endianness,
+        detection.confidence() * 100.0
+    );
+
+    Ok((best.game.into_config(), best.endianness == Endianness::Big))
+}
+
+/// Leaves out the bytes before `start` and the bytes after `end` counted from the end of the file,
+/// keeping them in a container so they can be put back after rebuilding.
+/// The container is little-endian until the byte order of the script is known
+fn slice_script(
+    in_bytes: Vec<u8>,
+    (start, end): (Option<usize>, Option<usize>),
+) -> AResult<(Vec<u8>, Container)> {
+    let file_length = in_bytes.len();
+
+    let start = start.unwrap_or(0);
+    let end = file_length.saturating_sub(end.unwrap_or(0));
+
+    let script = in_bytes
+        .get(start..end)
+        .ok_or_else(|| anyhow!("Offsets {start:#X}..{end:#X} are outside of the input file"))?
+        .to_owned();
+
+    Ok((
+        script,
+        Container::sliced(&in_bytes, start..end, Endianness::Little),
+    ))
+}
+
+fn endianness(big_endian: bool) -> Endianness {
+    if big_endian {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    }
+}
+
+fn add_length_fields(container: Option<&mut Container>, fields: Vec<LengthField>) -> AResult<()> {
+    let Some(container) = container else {
+        if fields.is_empty() {
+            return Ok(());
+        }
+        return Err(anyhow!(
+            "Length fields can only be used when the script is in a container or has a start offset"
+        ));
+    };
+
+    for field in fields {
+        if !container.add_length_field(field) {
+            return Err(anyhow!(
+                "Length field at {:#X} is outside of the {:#X} bytes before the script",
+                field.offset,
+                container.prefix.len()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Strips a known container from a script, if there is one
+fn unwrap_container(file: Vec<u8>) -> (Vec<u8>, Option<Container>) {
+    match Container::detect(&file) {
+        Some(container) => {
+            let range = container.script_range(file.len());
+            println!(
+                "Found {:?} container, script is at {:#X}..{:#X}",
+                container.kind, range.start, range.end
+            );
+
+            (file[range].to_owned(), Some(container))
+        }
+        None => (file, None),
+    }
+}
+
+fn load_uasset_pair(uasset: &Path) -> AResult<UassetPair> {
+    let uexp = uasset.with_extension("uexp");
+    if !uexp.is_file() {
+        return Err(BBScriptError::BadInputFile(uexp.to_string_lossy().into()).into());
+    }
+
+    Ok(UassetPair::new(
+        load_file(uasset.to_owned())?,
+        load_file(uexp)?,
+    )?)
+}
+
+/// Loads every file in a directory and its subdirectories
+fn load_dir(dir: PathBuf) -> AResult<Vec<Vec<u8>>> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(load_file(entry.into_path())?);
+        }
+    }
+
+    Ok(files)
+}
+
 /// Attempts to return a `Vec<u8>` of a files contents
 fn load_file(name: PathBuf) -> AResult<Vec<u8>> {
     let mut file = File::open(&name)?;
@@ -279,6 +1019,15 @@ fn parse_hex(input: &str) -> Result<usize, std::num::ParseIntError> {
     usize::from_str_radix(input, 16)
 }
 
+fn parse_length_field(input: &str) -> Result<LengthField, std::num::ParseIntError> {
+    let (offset, extra) = input.split_once('+').unwrap_or((input, "0"));
+
+    Ok(LengthField {
+        offset: parse_hex(offset)?,
+        extra: u32::from_str_radix(extra, 16)?,
+    })
+}
+
 /// Get a LevelFilter from -v occurences
 /// `Error` is excluded as the program doesn't call `log::error!()`
 fn log_level_from_verbosity(verbosity: u8) -> log::LevelFilter {
@@ -295,28 +1044,51 @@ fn log_level_from_verbosity(verbosity: u8) -> log::LevelFilter {
 
 fn run_parser(
     game: ScriptConfig,
-    in_path: PathBuf,
-    out_path: PathBuf,
-    byte_range: (Option<usize>, Option<usize>),
+    in_bytes: Vec<u8>,
+    out_path: &Path,
     big_endian: bool,
     indent_limit: usize,
+    recovery: ParseRecovery,
+    options: ScriptHeader,
 ) -> AResult<()> {
     let db = game;
 
-    let in_file = load_file(in_path)?;
-
-    let in_bytes = in_file;
-    let file_length = in_bytes.len();
-
-    let in_bytes =
-        in_bytes[byte_range.0.unwrap_or(0)..(file_length - byte_range.1.unwrap_or(0))].to_owned();
+    // the header only takes the settings the config can't know about from `options`
+    let with_options = |header: ScriptHeader| ScriptHeader {
+        container: options.container.clone(),
+        block_style: options.block_style,
+        aliases: options.aliases.clone(),
+        ..header
+    };
 
-    let result = if big_endian {
-        db.parse_to_string::<byteorder::BigEndian>(in_bytes, indent_limit)
+    let (result, issues) = if big_endian {
+        let header = with_options(ScriptHeader::new::<byteorder::BigEndian>(&db));
+        (
+            db.parse_to_string_with_header::<byteorder::BigEndian>(
+                &in_bytes,
+                indent_limit,
+                recovery,
+                &header,
+            ),
+            jump_table_issues::<byteorder::BigEndian>(&db, &in_bytes, recovery),
+        )
     } else {
-        db.parse_to_string::<byteorder::LittleEndian>(in_bytes, indent_limit)
+        let header = with_options(ScriptHeader::new::<byteorder::LittleEndian>(&db));
+        (
+            db.parse_to_string_with_header::<byteorder::LittleEndian>(
+                &in_bytes,
+                indent_limit,
+                recovery,
+                &header,
+            ),
+            jump_table_issues::<byteorder::LittleEndian>(&db, &in_bytes, recovery),
+        )
     };
 
+    if let Ok(issues) = issues {
+        report_jump_table_issues(&issues);
+    }
+
     match result {
         Ok(f) => {
             let mut output = File::create(out_path)?;
@@ -328,25 +1100,124 @@ fn run_parser(
     Ok(())
 }
 
+/// Path of an alias file as it's included from a script written to `output`,
+/// which is relative to the script if they are in the same directory
+fn include_path(aliases: &Path, output: &Path) -> AResult<String> {
+    let aliases = aliases.canonicalize()?;
+    let output_dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.canonicalize()?,
+        _ => std::env::current_dir()?,
+    };
+
+    Ok(match aliases.strip_prefix(output_dir) {
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => aliases.display().to_string(),
+    })
+}
+
+fn jump_table_issues<B: byteorder::ByteOrder>(
+    db: &ScriptConfig,
+    script: &[u8],
+    recovery: ParseRecovery,
+) -> Result<Vec<JumpTableIssue>, BBScriptError> {
+    let (jump_table, _) = db.read_jump_table::<B>(script)?;
+    let program = db.parse_located_with::<B>(script, recovery)?;
+
+    Ok(db.validate_jump_table(&jump_table, &program))
+}
+
+fn report_jump_table_issues(issues: &[JumpTableIssue]) {
+    for issue in issues {
+        println!("WARNING: {issue}");
+    }
+}
+
+fn run_detection(script: &[u8]) {
+    let detection = detect_game(script);
+
+    for candidate in &detection.candidates {
+        println!(
+            "{:?} ({:?} endian): score {:.2}, {:.0}% decoded, {:.0}% of jump table matched",
+            candidate.game,
+            candidate.endianness,
+            candidate.score,
+            candidate.decoded * 100.0,
+            candidate.jump_table_match * 100.0
+        );
+    }
+
+    match detection.best() {
+        Some(best) => println!(
+            "Best match: {:?} ({:?} endian) with {:.0}% confidence",
+            best.game,
+            best.endianness,
+            detection.confidence() * 100.0
+        ),
+        None => println!("The script doesn't decode with any supported game"),
+    }
+}
+
+/// Reads a readable script along with its header, and the config and byte order to rebuild it with.
+/// Both are taken from the header unless they are given on the command line
+fn load_readable_script(
+    input: &Path,
+    game: HeaderConfigArgs,
+    big_endian: bool,
+    little_endian: bool,
+) -> AResult<(String, ScriptHeader, ScriptConfig, bool)> {
+    let mut script = String::new();
+    File::open(input)?.read_to_string(&mut script)?;
+    let header = ScriptHeader::read(&script)?;
+
+    let game = match game.into_config_args() {
+        Some(game) => get_config(game)?,
+        None => header.game.map(SupportedGame::into_config).ok_or_else(|| {
+            anyhow!(
+                "The script's header doesn't name a game, specify one with --game or --config-file"
+            )
+        })?,
+    };
+    let big_endian = match (big_endian, little_endian) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => header.endianness == Some(Endianness::Big),
+    };
+
+    Ok((script, header, game, big_endian))
+}
+
 fn run_rebuilder(
     game: ScriptConfig,
-    input: PathBuf,
+    script: String,
+    input: &Path,
     output: PathBuf,
     big_endian: bool,
+    container: Option<Container>,
+    pac: Option<(PathBuf, String)>,
 ) -> AResult<()> {
     let db = game;
 
-    let mut script = String::new();
-    File::open(input)?.read_to_string(&mut script)?;
-
     let result = if big_endian {
-        rebuild_bbscript::<byteorder::BigEndian>(db, script)
+        rebuild_bbscript_at::<byteorder::BigEndian>(db, script, input)
     } else {
-        rebuild_bbscript::<byteorder::LittleEndian>(db, script)
+        rebuild_bbscript_at::<byteorder::LittleEndian>(db, script, input)
     };
 
     match result {
         Ok(f) => {
+            let f = match container {
+                Some(container) => container.wrap(&f),
+                None => f,
+            };
+            let f = match pac {
+                Some((archive, name)) => {
+                    let mut archive = Pac::parse(&load_file(archive)?)?;
+                    archive.insert(&name, f)?;
+                    archive.to_bytes()
+                }
+                None => f,
+            };
+
             let mut output = File::create(output)?;
             output.write_all(&f)?;
         }
@@ -355,21 +1226,336 @@ fn run_rebuilder(
     Ok(())
 }
 
+fn run_diff(
+    game: ScriptConfig,
+    old: &[u8],
+    new: &[u8],
+    big_endian: bool,
+    json: Option<PathBuf>,
+) -> AResult<()> {
+    let (old, new) = if big_endian {
+        (
+            game.parse_tree::<byteorder::BigEndian>(old)?,
+            game.parse_tree::<byteorder::BigEndian>(new)?,
+        )
+    } else {
+        (
+            game.parse_tree::<byteorder::LittleEndian>(old)?,
+            game.parse_tree::<byteorder::LittleEndian>(new)?,
+        )
+    };
+
+    let diff = diff_scripts(&game, &old, &new)?;
+
+    for node in &diff.added {
+        println!("+ {:?} {}", node.kind, node.name);
+    }
+    for node in &diff.removed {
+        println!("- {:?} {}", node.kind, node.name);
+    }
+    for node in &diff.modified {
+        println!("~ {:?} {}", node.kind, node.name);
+
+        for change in &node.changes {
+            match change {
+                InstructionChange::Added {
+                    new_index,
+                    instruction,
+                } => println!("    + [{new_index}] {instruction}"),
+                InstructionChange::Removed {
+                    old_index,
+                    instruction,
+                } => println!("    - [{old_index}] {instruction}"),
+                InstructionChange::Modified {
+                    old_index,
+                    new_index,
+                    name,
+                    args,
+                } => {
+                    let args: Vec<String> = args
+                        .iter()
+                        .map(|arg| {
+                            let old = arg.old.as_deref().unwrap_or("none");
+                            let new = arg.new.as_deref().unwrap_or("none");
+                            format!("arg {}: {old} -> {new}", arg.index)
+                        })
+                        .collect();
+
+                    println!(
+                        "    ~ [{old_index} -> {new_index}] {name}: {}",
+                        args.join(", ")
+                    );
+                }
+            }
+        }
+    }
+
+    if diff.is_empty() {
+        println!("No differences found");
+    }
+
+    if let Some(json) = json {
+        let mut output = File::create(json)?;
+        output.write_all(serde_json::to_string_pretty(&diff)?.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn run_merge(
+    game: ScriptConfig,
+    [base, ours, theirs]: [&[u8]; 3],
+    output: &Path,
+    big_endian: bool,
+    indent_limit: usize,
+) -> AResult<()> {
+    let trees = if big_endian {
+        [base, ours, theirs].map(|s| game.parse_tree_from_any::<byteorder::BigEndian>(s))
+    } else {
+        [base, ours, theirs].map(|s| game.parse_tree_from_any::<byteorder::LittleEndian>(s))
+    };
+    let [base, ours, theirs] = trees;
+
+    let merge = merge_scripts(&game, &base?, &ours?, &theirs?, indent_limit)?;
+
+    let mut file = File::create(output)?;
+    file.write_all(merge.text.as_bytes())?;
+
+    if merge.conflicts.is_empty() {
+        println!("Merged without conflicts");
+    } else {
+        for name in &merge.conflicts {
+            println!("CONFLICT in `{name}`");
+        }
+    }
+
+    Ok(())
+}
+
+fn make_patch(
+    game: ScriptConfig,
+    base: &[u8],
+    modded: &[u8],
+    output: &Path,
+    big_endian: bool,
+) -> AResult<()> {
+    let (base, modded) = if big_endian {
+        (
+            game.parse_tree_from_any::<byteorder::BigEndian>(base)?,
+            game.parse_tree_from_any::<byteorder::BigEndian>(modded)?,
+        )
+    } else {
+        (
+            game.parse_tree_from_any::<byteorder::LittleEndian>(base)?,
+            game.parse_tree_from_any::<byteorder::LittleEndian>(modded)?,
+        )
+    };
+
+    let patch = StatePatch::new(&game, &base, &modded)?;
+    println!("Patch contains {} changed states", patch.changes.len());
+
+    let mut file = File::create(output)?;
+    file.write_all(patch.to_string().as_bytes())?;
+
+    Ok(())
+}
+
+fn apply_patch(
+    game: ScriptConfig,
+    script: &[u8],
+    patch: &StatePatch,
+    container: Option<Container>,
+    output: &Path,
+    big_endian: bool,
+) -> AResult<()> {
+    let patched = if big_endian {
+        game.apply_patch::<byteorder::BigEndian>(script, patch)
+    } else {
+        game.apply_patch::<byteorder::LittleEndian>(script, patch)
+    }?;
+
+    let patched = match container {
+        Some(container) => container.wrap(&patched),
+        None => patched,
+    };
+
+    let mut file = File::create(output)?;
+    file.write_all(&patched)?;
+
+    Ok(())
+}
+
+fn run_verify(game: ConfigArgs, input: PathBuf, big_endian: bool) -> AResult<()> {
+    let files: Vec<PathBuf> = if input.is_file() {
+        vec![input]
+    } else if input.is_dir() {
+        walkdir::WalkDir::new(input)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect()
+    } else {
+        return Err(BBScriptError::BadInputFile(input.to_string_lossy().into()).into());
+    };
+
+    // with `--game auto` the config is detected for every file
+    let config = match game.game {
+        Some(GameSelection::Auto) => None,
+        _ => Some(get_config(game.clone())?),
+    };
+
+    let mut failed = 0;
+    for path in &files {
+        let file = load_file(path.clone())?;
+        let script = match Container::detect(&file) {
+            Some(container) => file[container.script_range(file.len())].to_owned(),
+            None => file,
+        };
+
+        let detected;
+        let (config, big_endian) = match &config {
+            Some(config) => (config, big_endian),
+            None => {
+                let (config, big_endian) = resolve_config(game.clone(), &script, big_endian)?;
+                detected = config;
+                (&detected, big_endian)
+            }
+        };
+
+        let result = if big_endian {
+            config.verify_round_trip::<byteorder::BigEndian>(&script)
+        } else {
+            config.verify_round_trip::<byteorder::LittleEndian>(&script)
+        };
+
+        let path = path.display();
+        match result {
+            Ok(None) => println!("OK   {path}"),
+            Ok(Some(mismatch)) => {
+                failed += 1;
+
+                let location = match (mismatch.state, mismatch.instruction) {
+                    (Some(state), Some((instruction, offset))) => {
+                        format!(" in state `{state}`, instruction `{instruction}` at {offset:#X}")
+                    }
+                    (Some(state), None) => format!(" in state `{state}`"),
+                    (None, _) if mismatch.in_jump_table => " in the jump table".into(),
+                    (None, _) => String::new(),
+                };
+
+                println!(
+                    "DIFF {path}: first difference at {:#X}{location} (original is {:#X} bytes, rebuilt is {:#X})",
+                    mismatch.offset, mismatch.original_len, mismatch.rebuilt_len
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {path}: {e}");
+            }
+        }
+    }
+
+    println!(
+        "{} of {} files round-trip exactly",
+        files.len() - failed,
+        files.len()
+    );
+
+    if failed > 0 {
+        return Err(anyhow!("{failed} files failed verification"));
+    }
+
+    Ok(())
+}
+
+fn extract_pac(input: PathBuf, out_dir: PathBuf, overwrite: bool) -> AResult<()> {
+    let pac = Pac::parse(&load_file(input)?)?;
+    std::fs::create_dir_all(&out_dir)?;
+
+    for entry in &pac.entries {
+        // names come from the archive, so they shouldn't be able to point outside of the output
+        let name = Path::new(&entry.name);
+        if name.components().count() != 1 || name.file_name().is_none() {
+            return Err(anyhow!(
+                "The archive contains an invalid file name `{}`",
+                entry.name
+            ));
+        }
+
+        let path = out_dir.join(name);
+        if path.exists() && !overwrite {
+            return Err(BBScriptError::OutputAlreadyExists(path.to_string_lossy().into()).into());
+        }
+
+        File::create(path)?.write_all(&entry.data)?;
+    }
+
+    Ok(())
+}
+
+fn repack_pac(input: PathBuf, files_dir: PathBuf, output: PathBuf) -> AResult<()> {
+    let mut pac = Pac::parse(&load_file(input)?)?;
+
+    for entry in std::fs::read_dir(files_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let name = entry.file_name();
+        let name = name
+            .to_str()
+            .ok_or_else(|| anyhow!("File name `{}` isn't valid UTF-8", name.to_string_lossy()))?;
+
+        if pac.get(name).is_none() {
+            log::info!("adding new file `{name}`");
+        }
+        pac.insert(name, load_file(entry.path())?)?;
+    }
+
+    File::create(output)?.write_all(&pac.to_bytes())?;
+
+    Ok(())
+}
+
+fn run_repack(
+    game: ScriptConfig,
+    script: String,
+    input: &Path,
+    pair: UassetPair,
+    output: PathBuf,
+    big_endian: bool,
+) -> AResult<()> {
+    let script = if big_endian {
+        rebuild_bbscript_at::<byteorder::BigEndian>(game, script, input)
+    } else {
+        rebuild_bbscript_at::<byteorder::LittleEndian>(game, script, input)
+    }?;
+
+    let pair = pair.replace_script(&script)?;
+
+    File::create(&output)?.write_all(&pair.uasset)?;
+    File::create(output.with_extension("uexp"))?.write_all(&pair.uexp)?;
+
+    Ok(())
+}
+
 fn run_structured_parser(
     game: ScriptConfig,
-    in_path: PathBuf,
+    in_bytes: Vec<u8>,
     out_path: PathBuf,
     big_endian: bool,
+    recovery: ParseRecovery,
 ) -> AResult<()> {
     let db = game;
 
-    let in_bytes = load_file(in_path)?;
-
     let result = if big_endian {
-        db.parse::<byteorder::BigEndian>(in_bytes)
+        db.parse_structured_with::<byteorder::BigEndian>(in_bytes, recovery)
     } else {
-        db.parse::<byteorder::LittleEndian>(in_bytes)
+        db.parse_structured_with::<byteorder::LittleEndian>(in_bytes, recovery)
     }?;
+    report_jump_table_issues(&result.jump_table_issues);
 
     let mut output = File::create(out_path)?;
 
@@ -379,52 +1565,135 @@ fn run_structured_parser(
     Ok(())
 }
 
+/// JSON scripts are either written by `parse-json`, or a bare list of instructions as written by older versions
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum JsonScript {
+    Structured(StructuredScript),
+    Program(Vec<InstructionValue>),
+}
+
+fn run_structured_rebuilder(
+    game: ScriptConfig,
+    input: PathBuf,
+    output: PathBuf,
+    big_endian: bool,
+) -> AResult<()> {
+    let db = game;
+
+    let mut script = String::new();
+    File::open(input)?.read_to_string(&mut script)?;
+
+    let program = match serde_json::from_str(&script)? {
+        JsonScript::Structured(script) => script.program,
+        JsonScript::Program(program) => program,
+    };
+
+    let result = if big_endian {
+        rebuild_instructions::<byteorder::BigEndian>(&db, program)
+    } else {
+        rebuild_instructions::<byteorder::LittleEndian>(&db, program)
+    }?;
+
+    let mut output = File::create(output)?;
+    output.write_all(&result)?;
+
+    Ok(())
+}
+
 fn update_sizes(
     new_sizes: ScriptConfig,
     mut config: ScriptConfig,
     out_path: PathBuf,
 ) -> AResult<()> {
-    let new_sizes = match new_sizes.instructions {
-        game_config::InstructionInfo::Sized(instructions) => instructions,
-        game_config::InstructionInfo::Unsized(_) => {
-            return Err(anyhow!("Cannot use unsized config for size diffing"))
-        }
-    };
+    config.update_sizes(new_sizes)?;
 
-    let main_config_instructions = match config.instructions {
-        game_config::InstructionInfo::Sized(ref mut instructions) => instructions,
-        game_config::InstructionInfo::Unsized(_) => {
-            return Err(anyhow!("Config must use sized instructions"))
-        }
-    };
+    let new_config = ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())?;
+    let mut output = File::create(out_path)?;
 
-    for (id, mut new_info) in new_sizes.into_iter() {
-        if let Some(instruction) = main_config_instructions.get_mut(&id) {
-            log::trace!("checking id {}", id);
-            if instruction.size != new_info.size {
-                log::info!("updating id {}", id);
-                instruction.size = new_info.size;
-
-                if instruction.size < 20 {
-                    log::debug!("args are small enough to fill with Number");
-                    instruction.set_args(&vec![
-                        crate::game_config::ArgType::Number;
-                        (instruction.size - 4) / 4
-                    ]);
-                } else {
-                    log::debug!("clearing args");
-                    instruction.set_args(&[]);
-                }
-            }
-        } else {
-            log::info!("adding new instruction {} with size {}", id, new_info.size);
-            if new_info.size < 20 {
-                new_info.set_args(&vec![game_config::ArgType::Number; (new_info.size - 4) / 4]);
-            }
-            main_config_instructions.insert(id, new_info);
+    output.write_all(new_config.as_bytes())?;
+
+    Ok(())
+}
+
+fn infer_sizes(
+    mut config: ScriptConfig,
+    in_dir: PathBuf,
+    out_path: PathBuf,
+    big_endian: bool,
+) -> AResult<()> {
+    let scripts = load_dir(in_dir)?;
+
+    let inference = if big_endian {
+        config.infer_sizes::<byteorder::BigEndian>(&scripts)
+    } else {
+        config.infer_sizes::<byteorder::LittleEndian>(&scripts)
+    }?;
+
+    println!(
+        "{} of {} states decode with the inferred sizes",
+        inference.decoded_states, inference.total_states
+    );
+    for (id, inferred) in &inference.sizes {
+        let previous = match inferred.previous_size {
+            Some(size) => format!("{size:#X}"),
+            None => "unknown".into(),
+        };
+
+        match inferred.size {
+            Some(size) => println!(
+                "ID {id}: {previous} -> {size:#X}, confidence {:.0}% ({} states decode, {} don't)",
+                inferred.confidence * 100.0,
+                inferred.votes,
+                inferred.conflicts
+            ),
+            None => println!("ID {id}: {previous} -> could not be inferred"),
         }
     }
 
+    inference.apply(&mut config)?;
+
+    let new_config = ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())?;
+    let mut output = File::create(out_path)?;
+
+    output.write_all(new_config.as_bytes())?;
+
+    Ok(())
+}
+
+fn infer_args(
+    config: ScriptConfig,
+    in_dir: PathBuf,
+    out_path: PathBuf,
+    big_endian: bool,
+) -> AResult<()> {
+    let scripts = load_dir(in_dir)?;
+
+    let patch = if big_endian {
+        config.infer_arg_types::<byteorder::BigEndian>(&scripts)
+    } else {
+        config.infer_arg_types::<byteorder::LittleEndian>(&scripts)
+    }?;
+
+    for (id, proposal) in &patch.instructions {
+        println!(
+            "ID {id} {}: {:?} from {} occurrences",
+            proposal.name, proposal.args, proposal.occurrences
+        );
+    }
+
+    let patch = ron::ser::to_string_pretty(&patch, ron::ser::PrettyConfig::default())?;
+    let mut output = File::create(out_path)?;
+
+    output.write_all(patch.as_bytes())?;
+
+    Ok(())
+}
+
+fn apply_arg_patch(patch: PathBuf, mut config: ScriptConfig, out_path: PathBuf) -> AResult<()> {
+    let patch: ArgPatch = ron::de::from_reader(File::open(patch)?)?;
+    patch.apply(&mut config)?;
+
     let new_config = ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())?;
     let mut output = File::create(out_path)?;
 
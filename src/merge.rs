@@ -0,0 +1,335 @@
+use crate::ast::{NodeId, Script};
+use crate::diff::{edit_script, lines, named_roots, Edit, Line, NodeKey};
+use crate::error::BBScriptError;
+use crate::game_config::ScriptConfig;
+use crate::parser::{LocatedInstruction, ReadableWriter};
+use crate::HashMap;
+
+pub const CONFLICT_OURS: &str = "<<<<<<< ours";
+pub const CONFLICT_SEPARATOR: &str = "=======";
+pub const CONFLICT_THEIRS: &str = ">>>>>>> theirs";
+
+/// A readable script merged from two scripts based on the same one
+#[derive(Debug, Clone)]
+pub struct Merge {
+    /// The merged script, with conflict markers around any instructions both sides changed differently
+    pub text: String,
+    /// Names of the states and subroutines containing conflicts
+    pub conflicts: Vec<String>,
+}
+
+/// Part of a merged state, either taken from one side or a conflict between both
+enum Segment<'a> {
+    Clean(Vec<&'a LocatedInstruction>),
+    Conflict(Vec<&'a LocatedInstruction>, Vec<&'a LocatedInstruction>),
+}
+
+/// A state or subroutine of one of the scripts, with its instructions rendered for comparing
+struct Side<'a> {
+    lines: Vec<Line>,
+    instructions: Vec<&'a LocatedInstruction>,
+}
+
+impl<'a> Side<'a> {
+    fn new(config: &ScriptConfig, script: &'a Script, id: NodeId) -> Result<Self, BBScriptError> {
+        Ok(Self {
+            lines: lines(config, script, id)?,
+            instructions: script.node_instructions(id),
+        })
+    }
+}
+
+enum Root {
+    Named(NodeKey),
+    /// Top level instructions without a name, like raw data, which are always taken from ours
+    Unnamed(NodeId),
+}
+
+/// Merges the changes `ours` and `theirs` made to `base`.
+/// States and subroutines are matched by name, and ones changed on both sides are merged instruction by instruction.
+/// A state is left as a single conflict if the merged instructions don't form a single block
+pub fn merge_scripts(
+    config: &ScriptConfig,
+    base: &Script,
+    ours: &Script,
+    theirs: &Script,
+    indent_limit: usize,
+) -> Result<Merge, BBScriptError> {
+    let base_roots: HashMap<NodeKey, NodeId> = named_roots(base).into_iter().collect();
+    let ours_named = named_roots(ours);
+    let theirs_named = named_roots(theirs);
+    let ours_roots: HashMap<NodeKey, NodeId> = ours_named.iter().cloned().collect();
+    let ours_keys: HashMap<NodeId, NodeKey> =
+        ours_named.into_iter().map(|(k, id)| (id, k)).collect();
+
+    // ours decides the order, with states only found in theirs placed after the state preceding them there
+    let mut order: Vec<Root> = ours
+        .roots()
+        .iter()
+        .map(|id| match ours_keys.get(id) {
+            Some(key) => Root::Named(key.clone()),
+            None => Root::Unnamed(*id),
+        })
+        .collect();
+
+    for (index, (key, _)) in theirs_named.iter().enumerate() {
+        if ours_roots.contains_key(key) {
+            continue;
+        }
+
+        let position = theirs_named[..index]
+            .iter()
+            .rev()
+            .find_map(|(previous, _)| {
+                order
+                    .iter()
+                    .position(|root| matches!(root, Root::Named(k) if k == previous))
+            })
+            .map_or(0, |position| position + 1);
+
+        order.insert(position, Root::Named(key.clone()));
+    }
+
+    let theirs_roots: HashMap<NodeKey, NodeId> = theirs_named.into_iter().collect();
+
+    let mut merge = Merge {
+        text: String::new(),
+        conflicts: Vec::new(),
+    };
+    let mut writer = ReadableWriter::new(indent_limit);
+
+    for root in order {
+        let segments = match &root {
+            Root::Unnamed(id) => vec![Segment::Clean(ours.node_instructions(*id))],
+            Root::Named(key) => {
+                let side = |script, roots: &HashMap<NodeKey, NodeId>| {
+                    roots
+                        .get(key)
+                        .map(|id| Side::new(config, script, *id))
+                        .transpose()
+                };
+
+                merge_node(
+                    config,
+                    side(base, &base_roots)?,
+                    side(ours, &ours_roots)?,
+                    side(theirs, &theirs_roots)?,
+                )?
+            }
+        };
+
+        if let Root::Named((_, name, _)) = &root {
+            if segments.iter().any(|s| matches!(s, Segment::Conflict(..))) {
+                merge.conflicts.push(name.clone());
+            }
+        }
+
+        for segment in segments {
+            match segment {
+                Segment::Clean(instructions) => {
+                    for instruction in instructions {
+                        writer.write(config, &mut merge.text, instruction)?;
+                    }
+                }
+                Segment::Conflict(ours, theirs) => {
+                    let before = writer.clone();
+
+                    merge.text.push_str(CONFLICT_OURS);
+                    merge.text.push('\n');
+                    for instruction in ours {
+                        writer.write(config, &mut merge.text, instruction)?;
+                    }
+
+                    merge.text.push_str(CONFLICT_SEPARATOR);
+                    merge.text.push('\n');
+                    let mut theirs_writer = before;
+                    for instruction in theirs {
+                        theirs_writer.write(config, &mut merge.text, instruction)?;
+                    }
+
+                    merge.text.push_str(CONFLICT_THEIRS);
+                    merge.text.push('\n');
+                }
+            }
+        }
+    }
+
+    Ok(merge)
+}
+
+fn merge_node<'a>(
+    config: &ScriptConfig,
+    base: Option<Side>,
+    ours: Option<Side<'a>>,
+    theirs: Option<Side<'a>>,
+) -> Result<Vec<Segment<'a>>, BBScriptError> {
+    let (base_lines, ours_lines, theirs_lines) =
+        (lines_of(&base), lines_of(&ours), lines_of(&theirs));
+    let all = |side: Option<Side<'a>>| side.map(|s| s.instructions).unwrap_or_default();
+
+    if base_lines == theirs_lines || ours_lines == theirs_lines {
+        return Ok(vec![Segment::Clean(all(ours))]);
+    }
+    if base_lines == ours_lines {
+        return Ok(vec![Segment::Clean(all(theirs))]);
+    }
+
+    let (ours, theirs) = match (ours, theirs) {
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        // deleted on one side and changed on the other
+        (ours, theirs) => return Ok(vec![Segment::Conflict(all(ours), all(theirs))]),
+    };
+
+    let segments = merge_instructions(base_lines.unwrap_or_default(), &ours, &theirs);
+
+    // a clean merge can still break the block structure, like when both sides add an `endIf`
+    if let [Segment::Clean(merged)] = &segments[..] {
+        let program = merged.iter().map(|i| (*i).clone()).collect();
+        if Script::new(config, program)?.roots().len() != 1 {
+            return Ok(vec![Segment::Conflict(
+                ours.instructions,
+                theirs.instructions,
+            )]);
+        }
+    }
+
+    Ok(segments)
+}
+
+fn lines_of<'s>(side: &'s Option<Side>) -> Option<&'s [Line]> {
+    side.as_ref().map(|s| s.lines.as_slice())
+}
+
+/// Three-way merge of the instructions of a state, keeping instructions that are unchanged on both sides as anchors
+fn merge_instructions<'a>(base: &[Line], ours: &Side<'a>, theirs: &Side<'a>) -> Vec<Segment<'a>> {
+    let matches = |other: &[Line]| {
+        let mut matched = vec![None; base.len()];
+        for edit in edit_script(base, other) {
+            if let Edit::Equal(b, o) = edit {
+                matched[b] = Some(o);
+            }
+        }
+        matched
+    };
+    let (ours_matches, theirs_matches) = (matches(&ours.lines), matches(&theirs.lines));
+
+    let mut segments = Vec::new();
+    let (mut b, mut o, mut t) = (0, 0, 0);
+
+    loop {
+        let (next_b, next_o, next_t) = (b..base.len())
+            .find_map(|i| Some((i, ours_matches[i]?, theirs_matches[i]?)))
+            .unwrap_or((base.len(), ours.lines.len(), theirs.lines.len()));
+
+        let (base_chunk, ours_chunk, theirs_chunk) = (
+            &base[b..next_b],
+            &ours.lines[o..next_o],
+            &theirs.lines[t..next_t],
+        );
+
+        if ours_chunk == theirs_chunk || theirs_chunk == base_chunk {
+            push_clean(&mut segments, &ours.instructions[o..next_o]);
+        } else if ours_chunk == base_chunk {
+            push_clean(&mut segments, &theirs.instructions[t..next_t]);
+        } else {
+            segments.push(Segment::Conflict(
+                ours.instructions[o..next_o].to_vec(),
+                theirs.instructions[t..next_t].to_vec(),
+            ));
+        }
+
+        if next_b == base.len() {
+            break;
+        }
+
+        push_clean(&mut segments, &ours.instructions[next_o..=next_o]);
+        (b, o, t) = (next_b + 1, next_o + 1, next_t + 1);
+    }
+
+    segments
+}
+
+fn push_clean<'a>(segments: &mut Vec<Segment<'a>>, instructions: &[&'a LocatedInstruction]) {
+    if let Some(Segment::Clean(last)) = segments.last_mut() {
+        last.extend_from_slice(instructions);
+    } else {
+        segments.push(Segment::Clean(instructions.to_vec()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::bytes;
+    use crate::merge::{merge_scripts, CONFLICT_OURS, CONFLICT_THEIRS};
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    const BASE: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  sprite: s32'nmc000_01', 5
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+endState:
+beginState: s32'CmnActJump'
+endState:
+";
+
+    const OURS: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 8
+  sprite: s32'nmc000_01', 5
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+endState:
+";
+
+    const THEIRS: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  sprite: s32'nmc000_01', 5
+  addPositionX: 1000, 0
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 4
+endState:
+beginState: s32'CmnActDash'
+endState:
+beginState: s32'CmnActJump'
+endState:
+";
+
+    const MERGED: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 8
+  sprite: s32'nmc000_01', 5
+  addPositionX: 1000, 0
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 4
+endState:
+beginState: s32'CmnActDash'
+endState:
+";
+
+    #[test]
+    fn merge_states() {
+        let config = SupportedGame::Ggst.into_config();
+        let tree = |source: &str| config.parse_tree::<LittleEndian>(bytes(source)).unwrap();
+
+        let merge = merge_scripts(&config, &tree(BASE), &tree(OURS), &tree(THEIRS), 12).unwrap();
+        assert!(merge.conflicts.is_empty());
+        assert_eq!(bytes(&merge.text), bytes(MERGED));
+
+        // both sides changing the same instruction differently only conflicts there
+        let conflicting = THEIRS.replace("'nmc000_00', 5", "'nmc000_00', 9");
+        let merge =
+            merge_scripts(&config, &tree(BASE), &tree(OURS), &tree(&conflicting), 12).unwrap();
+        assert_eq!(merge.conflicts, ["CmnActStand"]);
+        assert!(merge.text.contains(&format!(
+            "{CONFLICT_OURS}\n  sprite: s32'nmc000_00', 8\n=======\n  sprite: s32'nmc000_00', 9\n{CONFLICT_THEIRS}\n  sprite: s32'nmc000_01', 5\n  addPositionX"
+        )));
+    }
+}
@@ -0,0 +1,267 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::error::BBScriptError;
+
+pub const PAC_MAGIC: &[u8; 4] = b"FPAC";
+
+const HEADER_SIZE: usize = 0x20;
+/// Entries and file data start on multiples of this
+const ALIGNMENT: usize = 0x10;
+/// Size of the ID, offset and size following an entry's name
+const ENTRY_FIELDS_SIZE: usize = 0xC;
+
+/// A file stored in an FPAC archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacEntry {
+    pub name: String,
+    pub id: u32,
+    pub data: Vec<u8>,
+}
+
+/// An FPAC `.pac` archive, which ArcSys games store character scripts and other files in
+#[derive(Debug, Clone)]
+pub struct Pac {
+    /// Header flags, kept as they are when the archive is written again
+    pub flags: u32,
+    /// Space reserved for each name, including the terminating zero
+    pub name_length: usize,
+    /// Whether each entry stores a hash of its name after its size
+    pub hashed: bool,
+    pub entries: Vec<PacEntry>,
+}
+
+/// Hash of a file name as stored in FPAC entries
+pub fn name_hash(name: &str) -> u32 {
+    name.bytes().fold(0u32, |hash, c| {
+        hash.wrapping_mul(0x89)
+            .wrapping_add(u32::from(c.to_ascii_lowercase()))
+    })
+}
+
+const fn align(value: usize) -> usize {
+    value.div_ceil(ALIGNMENT) * ALIGNMENT
+}
+
+impl Pac {
+    pub fn parse(input: &[u8]) -> Result<Self, BBScriptError> {
+        if input.len() < HEADER_SIZE || !input.starts_with(PAC_MAGIC) {
+            return Err(invalid("missing FPAC header"));
+        }
+
+        let read = |offset: usize| LittleEndian::read_u32(&input[offset..offset + 4]) as usize;
+
+        let data_start = read(0x4);
+        let count = read(0xC);
+        let flags = read(0x10) as u32;
+        let name_length = read(0x14);
+        // names are zero terminated, so there has to be room for at least the terminator
+        if name_length == 0 {
+            return Err(invalid("name length is 0"));
+        }
+
+        let entry_size = entry_size(name_length);
+        let entries_end = count
+            .checked_mul(entry_size)
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .filter(|end| *end <= input.len() && *end <= data_start)
+            .ok_or_else(|| invalid("entries don't fit in the archive"))?;
+
+        let mut pac = Self {
+            flags,
+            name_length,
+            hashed: count > 0,
+            entries: Vec::with_capacity(count),
+        };
+
+        for entry in input[HEADER_SIZE..entries_end].chunks_exact(entry_size) {
+            let (name, fields) = entry.split_at(name_length);
+            let name_end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            let name = String::from_utf8_lossy(&name[..name_end]).into_owned();
+
+            let id = LittleEndian::read_u32(&fields[0x0..]);
+            let offset = LittleEndian::read_u32(&fields[0x4..]) as usize;
+            let size = LittleEndian::read_u32(&fields[0x8..]) as usize;
+
+            // the hash takes up space that would otherwise be padding
+            let hash = fields.get(0xC..0x10).map(LittleEndian::read_u32);
+            pac.hashed &= hash == Some(name_hash(&name));
+
+            let data = data_start
+                .checked_add(offset)
+                .and_then(|start| input.get(start..start.checked_add(size)?))
+                .ok_or_else(|| invalid(&format!("data of `{name}` is outside of the archive")))?;
+
+            pac.entries.push(PacEntry {
+                name,
+                id,
+                data: data.to_vec(),
+            });
+        }
+
+        Ok(pac)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PacEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Replaces the data of a file, or adds it as a new file if the archive doesn't contain it.
+    /// New files are put in hash order in hashed archives, so games can still binary search them
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) -> Result<(), BBScriptError> {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.name == name) {
+            entry.data = data;
+            return Ok(());
+        }
+
+        if name.len() >= self.name_length {
+            return Err(invalid(&format!(
+                "name `{name}` is longer than the {} bytes the archive allows",
+                self.name_length.saturating_sub(1)
+            )));
+        }
+
+        let id = match self.entries.iter().map(|e| e.id).max() {
+            Some(id) => id
+                .checked_add(1)
+                .ok_or_else(|| invalid("no file IDs are left"))?,
+            None => 0,
+        };
+        let position = if self.hashed {
+            let hash = name_hash(name);
+            self.entries.partition_point(|e| name_hash(&e.name) < hash)
+        } else {
+            self.entries.len()
+        };
+
+        self.entries.insert(
+            position,
+            PacEntry {
+                name: name.into(),
+                id,
+                data,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let entry_size = entry_size(self.name_length);
+        let data_start = align(HEADER_SIZE + self.entries.len() * entry_size);
+        let total_size = data_start
+            + self
+                .entries
+                .iter()
+                .map(|e| align(e.data.len()))
+                .sum::<usize>();
+
+        let mut out = vec![0; data_start];
+        out[..4].copy_from_slice(PAC_MAGIC);
+        LittleEndian::write_u32(&mut out[0x4..], data_start as u32);
+        LittleEndian::write_u32(&mut out[0x8..], total_size as u32);
+        LittleEndian::write_u32(&mut out[0xC..], self.entries.len() as u32);
+        LittleEndian::write_u32(&mut out[0x10..], self.flags);
+        LittleEndian::write_u32(&mut out[0x14..], self.name_length as u32);
+
+        let mut offset = 0;
+        for (index, entry) in self.entries.iter().enumerate() {
+            let start = HEADER_SIZE + index * entry_size;
+            let slot = &mut out[start..start + entry_size];
+
+            let name = entry.name.as_bytes();
+            let name_len = name.len().min(self.name_length.saturating_sub(1));
+            slot[..name_len].copy_from_slice(&name[..name_len]);
+
+            let fields = &mut slot[self.name_length..];
+            LittleEndian::write_u32(&mut fields[0x0..], entry.id);
+            LittleEndian::write_u32(&mut fields[0x4..], offset as u32);
+            LittleEndian::write_u32(&mut fields[0x8..], entry.data.len() as u32);
+            if self.hashed {
+                LittleEndian::write_u32(&mut fields[0xC..], name_hash(&entry.name));
+            }
+
+            offset += align(entry.data.len());
+        }
+
+        out.reserve(total_size - data_start);
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.data);
+            out.resize(align(out.len()), 0);
+        }
+
+        out
+    }
+}
+
+/// Entries are padded so each one starts on an aligned offset
+fn entry_size(name_length: usize) -> usize {
+    align(name_length + ENTRY_FIELDS_SIZE)
+}
+
+fn invalid(reason: &str) -> BBScriptError {
+    BBScriptError::InvalidPac(reason.into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pac::{name_hash, Pac, PacEntry};
+
+    fn pac(hashed: bool) -> Pac {
+        let mut entries: Vec<PacEntry> = ["scr_ha.bin", "scr_haea.bin", "scr_ha_col.bin"]
+            .into_iter()
+            .enumerate()
+            .map(|(id, name)| PacEntry {
+                name: name.into(),
+                id: id as u32,
+                data: name.repeat(id + 1).into_bytes(),
+            })
+            .collect();
+
+        if hashed {
+            entries.sort_by_key(|e| name_hash(&e.name));
+        }
+
+        Pac {
+            flags: 1,
+            name_length: 0x20,
+            hashed,
+            entries,
+        }
+    }
+
+    #[test]
+    fn pac_round_trip() {
+        for hashed in [false, true] {
+            let bytes = pac(hashed).to_bytes();
+            let parsed = Pac::parse(&bytes).unwrap();
+
+            assert_eq!(parsed.hashed, hashed);
+            assert_eq!(parsed.entries, pac(hashed).entries);
+            assert_eq!(parsed.to_bytes(), bytes);
+            assert_eq!(bytes.len() % 0x10, 0);
+        }
+
+        let mut no_names = pac(false).to_bytes();
+        no_names[0x14..0x18].fill(0);
+        assert!(Pac::parse(&no_names).is_err());
+    }
+
+    #[test]
+    fn pac_insert() {
+        let mut pac = pac(true);
+        pac.insert("scr_ha.bin", vec![1; 5]).unwrap();
+        pac.insert("scr_new.bin", vec![2; 17]).unwrap();
+        assert!(pac.insert(&"x".repeat(0x20), Vec::new()).is_err());
+
+        let parsed = Pac::parse(&pac.to_bytes()).unwrap();
+        assert_eq!(parsed.get("scr_ha.bin").unwrap().data, vec![1; 5]);
+        assert_eq!(parsed.get("scr_new.bin").unwrap().id, 3);
+        assert!(parsed
+            .entries
+            .windows(2)
+            .all(|w| name_hash(&w[0].name) <= name_hash(&w[1].name)));
+
+        pac.entries[0].id = u32::MAX;
+        assert!(pac.insert("scr_other.bin", Vec::new()).is_err());
+    }
+}
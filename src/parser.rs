@@ -4,12 +4,14 @@ use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
 use std::fmt::Write;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 
+use crate::ast::Script;
 use crate::game_config::{
-    ArgType, BBSNumber, CodeBlock, Instruction, ScriptConfig, SizedInstruction, SizedString,
-    TaggedValue, UnsizedInstruction,
+    ArgType, BBSNumber, CodeBlock, GenericInstruction, Instruction, ScriptConfig, SizedInstruction,
+    SizedString, TaggedValue, UnsizedInstruction,
 };
+use crate::header::{BlockStyle, ScriptHeader};
 use crate::BBScriptError;
 use crate::HashMap;
 
@@ -25,10 +27,95 @@ pub enum ArgValue {
     Enum(String, BBSNumber),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum InstructionIdentifier {
     Name(String),
     Id(u32),
+    /// Pseudo-instruction holding bytes that could not be decoded, which are rebuilt verbatim.
+    /// The first arg is [`ArgValue::Unknown`] with the data, followed by a
+    /// [`ArgValue::Number`] jump table ID, [`ArgValue::String32`] name and [`ArgValue::Number`] offset
+    /// for each jump table entry pointing inside of the data
+    RawData,
+    /// Pseudo-instruction recording the original jump table of a script, for tables the rebuilder wouldn't generate as-is.
+    /// Each entry is a [`ArgValue::Number`] table ID, [`ArgValue::String32`] name and [`ArgValue::Number`] index
+    /// of the state it points to among the states with that name
+    JumpTable,
+}
+
+/// Name of the [`InstructionIdentifier::RawData`] pseudo-instruction in readable scripts
+pub const RAW_DATA_NAME: &str = "#raw";
+/// Name of the [`InstructionIdentifier::JumpTable`] pseudo-instruction in readable scripts
+pub const JUMP_TABLE_NAME: &str = "#jumpTable";
+
+/// How to handle script data that can't be decoded with the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ParseRecovery {
+    /// Fail on the first instruction that can't be decoded
+    #[default]
+    Strict,
+    /// Keep everything decoded up to the first failure, and store the rest as an [`InstructionIdentifier::RawData`] instruction
+    RawTail,
+    /// Skip over unknown instructions in sized configs by searching for the next offset that decodes cleanly.
+    /// The skipped bytes are kept as an `UnknownN` instruction, and any other failure is handled like [`ParseRecovery::RawTail`]
+    Resync,
+}
+
+/// Number of instructions that must decode after a resync candidate when there is no jump table entry to check against
+const RESYNC_RUN_LENGTH: usize = 8;
+
+/// An entry of the jump table in a script header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JumpEntry {
+    /// The ID of the instruction this entry belongs to in the config's `jump_table_ids`
+    pub table_id: u32,
+    pub name: SizedString<32>,
+    /// Offset from the start of the script data
+    pub offset: u32,
+}
+
+/// A jump table entry that doesn't point at the start of a state with the same name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JumpTableIssue {
+    pub entry: JumpEntry,
+    pub problem: JumpTableProblem,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum JumpTableProblem {
+    /// The entry points past the end of the script, or at an instruction that isn't in `jump_table_ids`
+    Orphan,
+    /// The entry points at a state with a different name
+    NameMismatch(String),
+    /// The entry points inside of the instruction starting at this offset
+    MidInstruction(usize),
+}
+
+impl std::fmt::Display for JumpTableIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let JumpEntry { name, offset, .. } = &self.entry;
+        write!(f, "jump table entry `{name}` at offset {offset:#X} ")?;
+
+        match &self.problem {
+            JumpTableProblem::Orphan => write!(f, "doesn't point at a state"),
+            JumpTableProblem::NameMismatch(found) => write!(f, "points at state `{found}`"),
+            JumpTableProblem::MidInstruction(start) => {
+                write!(f, "points inside of the instruction at offset {start:#X}")
+            }
+        }
+    }
+}
+
+/// A parsed script along with its jump table, as written by the `parse-json` command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuredScript {
+    /// The jump table as it is stored in the script, only for reference.
+    /// Rebuilding generates the table again, keeping the original order if `program` starts with a
+    /// [`InstructionIdentifier::JumpTable`] instruction
+    pub jump_table: Vec<JumpEntry>,
+    #[serde(default)]
+    pub jump_table_issues: Vec<JumpTableIssue>,
+    pub program: Vec<InstructionValue>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,22 +124,217 @@ pub struct InstructionValue {
     pub args: SmallVec<[ArgValue; 8]>,
 }
 
-fn arg_to_string(config: &ScriptConfig, arg: &ArgValue) -> Result<String, BBScriptError> {
+/// An [`InstructionValue`] along with where it was found in the script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocatedInstruction {
+    /// Offset from the start of the script data, which is the same base used by the jump table
+    pub offset: usize,
+    /// Size of the instruction in bytes, including its ID
+    pub size: usize,
+    pub instruction: InstructionValue,
+}
+
+/// Writes instructions in the readable format, indenting them by the blocks they are in
+#[derive(Debug, Clone)]
+pub(crate) struct ReadableWriter {
+    indent_limit: usize,
+    indent: usize,
+    last_block_type: Option<String>,
+    last_block_type_valid: bool,
+    braced: BracedBlocks,
+}
+
+impl ReadableWriter {
+    pub(crate) fn new(indent_limit: usize) -> Self {
+        Self {
+            indent_limit,
+            indent: 0,
+            last_block_type: None,
+            last_block_type_valid: false,
+            braced: BracedBlocks::default(),
+        }
+    }
+
+    /// Writes the given blocks with braces instead of their end instructions
+    pub(crate) fn with_braces(mut self, braced: BracedBlocks) -> Self {
+        self.braced = braced;
+        self
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        config: &ScriptConfig,
+        out: &mut String,
+        located: &LocatedInstruction,
+    ) -> Result<(), BBScriptError> {
+        let LocatedInstruction {
+            instruction, size, ..
+        } = located;
+
+        if matches!(
+            instruction.identifier,
+            InstructionIdentifier::RawData | InstructionIdentifier::JumpTable
+        ) {
+            out.write_fmt(format_args!(
+                "{}: ",
+                instruction_name(config, &instruction.identifier)
+            ))?;
+            write_args(config, out, &instruction.args)?;
+            out.write_char('\n')?;
+
+            return Ok(());
+        }
+
+        let instruction_info = config.get_by_identifier(&instruction.identifier)?;
+        let mut block_ended = false;
+
+        match instruction_info.block_type() {
+            CodeBlock::BeginNonrecursive
+                if self.last_block_type_valid
+                    && self.last_block_type == instruction_info.name()
+                    && self.indent > 0 =>
+            {
+                self.indent -= 1;
+                self.last_block_type_valid = false;
+            }
+            CodeBlock::End if self.indent > 0 => {
+                self.last_block_type_valid = false;
+                self.indent -= 1;
+                if self.indent < 1 {
+                    self.indent = 1;
+                }
+            }
+            CodeBlock::EndState if self.indent > 0 => {
+                self.last_block_type_valid = false;
+                self.indent = 0;
+                block_ended = true;
+            }
+            _ => {}
+        }
+
+        // indent the text
+        out.write_fmt(format_args!(
+            "{:indent$}",
+            "",
+            indent = (self.indent.clamp(0, self.indent_limit) * (INDENT_SPACES))
+        ))?;
+
+        let instruction_name = if let Some(name) = instruction_info.name() {
+            name
+        } else {
+            format!("Unknown{}", instruction_info.id())
+        };
+
+        if self.braced.ends.contains(&located.offset) {
+            out.write_char('}')?;
+        } else {
+            match expression_to_string(config, &instruction_name, &instruction.args)? {
+                Some(expression) => out.write_str(&expression)?,
+                None => {
+                    out.write_fmt(format_args!("{}: ", instruction_name))?;
+                    write_args(config, out, &instruction.args)?;
+                }
+            }
+
+            if self.braced.begins.contains(&located.offset) {
+                out.truncate(out.trim_end_matches(' ').len());
+                out.write_str(" {")?;
+            }
+
+            // only instructions skipped by `ParseRecovery::Resync` can be missing from a sized config
+            if !config.is_unsized() && instruction_info.size().is_none() {
+                out.write_fmt(format_args!(" // inferred size: {size}"))?;
+            }
+        }
+
+        out.write_char('\n')?;
+
+        match instruction_info.block_type() {
+            CodeBlock::BeginNonrecursive | CodeBlock::Begin => {
+                self.indent += 1;
+                self.last_block_type = instruction_info.name();
+                self.last_block_type_valid = true;
+            }
+            _ => {}
+        }
+
+        if block_ended {
+            out.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Offsets of the instructions opening and closing blocks that can be written with braces,
+/// which are the blocks closed by the end instruction in the config's `block_ends` without any args
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BracedBlocks {
+    begins: std::collections::HashSet<usize>,
+    ends: std::collections::HashSet<usize>,
+}
+
+impl BracedBlocks {
+    pub(crate) fn new(
+        config: &ScriptConfig,
+        program: &[LocatedInstruction],
+    ) -> Result<Self, BBScriptError> {
+        let script = Script::new(config, program.to_vec())?;
+        let mut braced = Self::default();
+
+        for node in script.nodes() {
+            let Some(end) = &node.end else {
+                continue;
+            };
+
+            let begin_name = instruction_name(config, &node.begin.instruction.identifier);
+            let closes = config
+                .block_ends
+                .get(&begin_name)
+                .is_some_and(|name| *name == instruction_name(config, &end.instruction.identifier));
+
+            if closes && end.instruction.args.is_empty() {
+                braced.begins.insert(node.begin.offset);
+                braced.ends.insert(end.offset);
+            }
+        }
+
+        Ok(braced)
+    }
+}
+
+/// Name of an instruction as it is written in readable scripts
+pub(crate) fn instruction_name(
+    config: &ScriptConfig,
+    identifier: &InstructionIdentifier,
+) -> String {
+    match (config.get_by_identifier(identifier), identifier) {
+        (Ok(info), _) => info
+            .name()
+            .unwrap_or_else(|| format!("Unknown{}", info.id())),
+        (Err(_), InstructionIdentifier::Name(name)) => name.clone(),
+        (Err(_), InstructionIdentifier::Id(id)) => format!("Unknown{id}"),
+        (Err(_), InstructionIdentifier::RawData) => RAW_DATA_NAME.into(),
+        (Err(_), InstructionIdentifier::JumpTable) => JUMP_TABLE_NAME.into(),
+    }
+}
+
+pub(crate) fn arg_to_string(
+    config: &ScriptConfig,
+    arg: &ArgValue,
+) -> Result<String, BBScriptError> {
     match arg {
         ArgValue::Unknown(data) => Ok(format!("0x{}", hex::encode_upper(data))),
         ArgValue::Number(num) => Ok(format!("{num}")),
-        ArgValue::String16(s) => Ok(format!("s16'{s}'")),
-        ArgValue::String32(s) => Ok(format!("s32'{s}'")),
+        ArgValue::String16(s) => Ok(format!("s16'{}'", escaped(&s.0))),
+        ArgValue::String32(s) => Ok(format!("s32'{}'", escaped(&s.0))),
         ArgValue::AccessedValue(_tagged @ TaggedValue::Improper { tag, value }) => {
             Ok(format!("BadTag({tag}, {value})"))
         }
         // get named value
         ArgValue::AccessedValue(_tagged @ TaggedValue::Variable(val)) => Ok(format!(
             "Mem({})",
-            config
-                .named_variables
-                .get_by_left(val)
-                .unwrap_or(&val.to_string())
+            config.variable_name(*val).unwrap_or(&val.to_string())
         )),
         ArgValue::AccessedValue(_tagged @ TaggedValue::Literal(val)) => Ok(format!("Val({val})")),
         ArgValue::Enum(name, val) => match config.named_value_maps.get(name) {
@@ -64,162 +346,677 @@ fn arg_to_string(config: &ScriptConfig, arg: &ArgValue) -> Result<String, BBScri
     }
 }
 
+/// Writes an instruction as an infix expression if the config has a syntax for it,
+/// see [`ExpressionSyntax`](crate::game_config::ExpressionSyntax)
+fn expression_to_string(
+    config: &ScriptConfig,
+    name: &str,
+    args: &[ArgValue],
+) -> Result<Option<String>, BBScriptError> {
+    let syntax = &config.expressions;
+    let keyword = syntax.conditions.get(name);
+    if keyword.is_none() && syntax.assignment.as_deref() != Some(name) {
+        return Ok(None);
+    }
+
+    let index = args
+        .iter()
+        .position(|arg| matches!(arg, ArgValue::Enum(name, _) if *name == syntax.operation_enum));
+    let Some(index) = index else {
+        return Ok(None);
+    };
+
+    let (
+        ArgValue::Enum(enum_name, operation),
+        Some(ArgValue::AccessedValue(lhs)),
+        Some(ArgValue::AccessedValue(rhs)),
+    ) = (&args[index], args.get(index + 1), args.get(index + 2))
+    else {
+        return Ok(None);
+    };
+
+    let operators = match keyword {
+        Some(_) => &syntax.condition_operators,
+        None => &syntax.assignment_operators,
+    };
+    let operator = config
+        .named_value_maps
+        .get(enum_name)
+        .and_then(|map| map.get_by_left(operation))
+        .and_then(|variant| operators.get(variant));
+    let Some(operator) = operator else {
+        return Ok(None);
+    };
+
+    let (lhs, rhs) = (
+        operand_to_string(config, lhs),
+        operand_to_string(config, rhs),
+    );
+    let mut out = match keyword {
+        Some(keyword) => format!("{keyword} ({lhs} {operator} {rhs})"),
+        None => format!("{lhs} {operator} {rhs}"),
+    };
+
+    // args that aren't part of the expression are written after it like normal args
+    let rest: Vec<ArgValue> = args[..index]
+        .iter()
+        .chain(&args[index + 3..])
+        .cloned()
+        .collect();
+    if !rest.is_empty() {
+        out.write_str(": ")?;
+        write_args(config, &mut out, &rest)?;
+    }
+
+    Ok(Some(out))
+}
+
+/// Writes an operand of an expression, with variables that are valid identifiers and literals written bare
+fn operand_to_string(config: &ScriptConfig, value: &TaggedValue) -> String {
+    match value {
+        TaggedValue::Literal(val) => format!("{val}"),
+        TaggedValue::Variable(var) => match config.variable_name(*var) {
+            Some(name) if is_identifier(name) => name.clone(),
+            Some(name) => format!("Mem({name})"),
+            None => format!("Mem({var})"),
+        },
+        TaggedValue::Improper { tag, value } => format!("BadTag({tag},{value})"),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn write_args(
+    config: &ScriptConfig,
+    out: &mut String,
+    args: &[ArgValue],
+) -> Result<(), BBScriptError> {
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        out.write_fmt(format_args!("{}", arg_to_string(config, arg)?))?;
+
+        if args.peek().is_some() {
+            out.write_fmt(format_args!(", "))?;
+        }
+    }
+
+    Ok(())
+}
+
 impl ScriptConfig {
+    /// Looks up the config entry for an instruction that was already parsed.
+    /// IDs missing from the config get an empty [`UnsizedInstruction`], the same as unknown instructions in unsized configs
+    pub fn get_by_identifier(
+        &self,
+        identifier: &InstructionIdentifier,
+    ) -> Result<GenericInstruction, BBScriptError> {
+        match identifier {
+            InstructionIdentifier::Name(name) => self
+                .get_by_name(name)
+                .ok_or_else(|| BBScriptError::UnknownInstructionName(name.clone())),
+            InstructionIdentifier::Id(id) => Ok(self
+                .get_by_id(*id)
+                .unwrap_or_else(|| GenericInstruction::Unsized(*id, UnsizedInstruction::new()))),
+            InstructionIdentifier::RawData => Err(BBScriptError::UnknownInstructionName(
+                RAW_DATA_NAME.to_string(),
+            )),
+            InstructionIdentifier::JumpTable => Err(BBScriptError::UnknownInstructionName(
+                JUMP_TABLE_NAME.to_string(),
+            )),
+        }
+    }
+
     pub fn parse_to_string<B: ByteOrder>(
         &self,
         input: impl AsRef<[u8]>,
         indent_limit: usize,
     ) -> Result<String, BBScriptError> {
-        let program = self.parse::<B>(input.as_ref())?;
+        self.parse_to_string_with::<B>(input, indent_limit, ParseRecovery::Strict)
+    }
+
+    /// Same as [`ScriptConfig::parse_to_string`], using `recovery` to handle data that can't be decoded
+    pub fn parse_to_string_with<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        indent_limit: usize,
+        recovery: ParseRecovery,
+    ) -> Result<String, BBScriptError> {
+        let header = ScriptHeader::new::<B>(self);
+        self.parse_to_string_with_header::<B>(input, indent_limit, recovery, &header)
+    }
+
+    /// Same as [`ScriptConfig::parse_to_string_with`], writing `header` at the top of the script
+    /// instead of the one for this config
+    pub fn parse_to_string_with_header<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        indent_limit: usize,
+        recovery: ParseRecovery,
+        header: &ScriptHeader,
+    ) -> Result<String, BBScriptError> {
+        let input = input.as_ref();
+        let (jump_table, base) = self.read_jump_table::<B>(input)?;
+        let program = self.parse_script::<B>(&input[base..], base, recovery, &jump_table)?;
         let mut out = String::new();
 
-        let mut last_block_type: Option<String> = None;
-        let mut last_block_type_valid = false;
-        let mut indent = 0;
-        let mut block_ended = false;
-        for instruction in program {
-            let instruction_info = match instruction.identifier {
-                InstructionIdentifier::Name(name) => self
-                    .get_by_name(&name)
-                    .ok_or(BBScriptError::UnknownInstructionName(name)),
-                InstructionIdentifier::Id(id) => self
-                    .get_by_id(id)
-                    .ok_or(BBScriptError::UnknownInstructionID(id)),
-            }?;
-
-            match instruction_info.block_type() {
-                CodeBlock::BeginNonrecursive => {
-                    if last_block_type_valid && last_block_type == instruction_info.name() && indent > 0 {
-                        indent -= 1;
-                        last_block_type_valid = false;
-                    }
-                },
-                CodeBlock::End => {
-                    if indent > 0 {
-                        last_block_type_valid = false;
-                        indent -= 1;
-                        if indent < 1 {
-                            indent = 1;
+        if !header.is_empty() {
+            out.write_fmt(format_args!("{header}\n"))?;
+        }
+
+        if let Some(jump_table) = self.recorded_jump_table(&jump_table, &program) {
+            out.write_fmt(format_args!("{JUMP_TABLE_NAME}: "))?;
+            write_args(self, &mut out, &jump_table.args)?;
+            out.write_str("\n\n")?;
+        }
+
+        let mut writer = ReadableWriter::new(indent_limit);
+        if header.block_style == Some(BlockStyle::Braces) {
+            writer = writer.with_braces(BracedBlocks::new(self, &program)?);
+        }
+
+        for located in &program {
+            writer.write(self, &mut out, located)?;
+        }
+
+        Ok(out)
+    }
+
+    pub fn parse<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Vec<InstructionValue>, BBScriptError> {
+        Ok(self
+            .parse_structured_with::<B>(input, ParseRecovery::Strict)?
+            .program)
+    }
+
+    /// Parses a script along with its jump table, checking every entry with [`ScriptConfig::validate_jump_table`]
+    pub fn parse_structured_with<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        recovery: ParseRecovery,
+    ) -> Result<StructuredScript, BBScriptError> {
+        let input = input.as_ref();
+        let (jump_table, base) = self.read_jump_table::<B>(input)?;
+        let located = self.parse_script::<B>(&input[base..], base, recovery, &jump_table)?;
+
+        let jump_table_issues = self.validate_jump_table(&jump_table, &located);
+        let program = self
+            .recorded_jump_table(&jump_table, &located)
+            .into_iter()
+            .chain(located.into_iter().map(|i| i.instruction))
+            .collect();
+
+        Ok(StructuredScript {
+            jump_table,
+            jump_table_issues,
+            program,
+        })
+    }
+
+    /// Checks that every jump table entry points at the start of a state with the same name.
+    /// Entries pointing inside of raw data can't be checked and are skipped
+    pub fn validate_jump_table(
+        &self,
+        jump_table: &[JumpEntry],
+        program: &[LocatedInstruction],
+    ) -> Vec<JumpTableIssue> {
+        let mut issues = Vec::new();
+
+        for entry in jump_table {
+            let offset = entry.offset as usize;
+            let containing = program
+                .partition_point(|i| i.offset <= offset)
+                .checked_sub(1)
+                .map(|index| &program[index])
+                .filter(|i| offset < i.offset + i.size);
+
+            let problem = match containing {
+                None => Some(JumpTableProblem::Orphan),
+                Some(located)
+                    if located.instruction.identifier == InstructionIdentifier::RawData =>
+                {
+                    None
+                }
+                Some(located) if located.offset != offset => {
+                    Some(JumpTableProblem::MidInstruction(located.offset))
+                }
+                Some(located) => {
+                    let is_state = self
+                        .get_by_identifier(&located.instruction.identifier)
+                        .is_ok_and(|info| self.is_jump_entry_id(info.id()));
+
+                    match located.instruction.args.first() {
+                        Some(ArgValue::String32(name)) if is_state && name.0 != entry.name.0 => {
+                            Some(JumpTableProblem::NameMismatch(name.0.clone()))
                         }
+                        Some(ArgValue::String32(_)) if is_state => None,
+                        _ => Some(JumpTableProblem::Orphan),
                     }
-                },
-                CodeBlock::EndState => {
-                    if indent > 0 {
-                        last_block_type_valid = false;
-                        indent = 0;
-                        block_ended = true;
-                    }
-                },
-                _ => {}
-            }
+                }
+            };
 
-            // indent the text
-            out.write_fmt(format_args!(
-                "{:indent$}",
-                "",
-                indent = (indent.clamp(0, indent_limit) * (INDENT_SPACES))
-            ))?;
+            if let Some(problem) = problem {
+                issues.push(JumpTableIssue {
+                    entry: entry.clone(),
+                    problem,
+                });
+            }
+        }
 
-            let instruction_name = if let Some(name) = instruction_info.name() {
-                name
-            } else {
-                format!("Unknown{}", instruction_info.id())
-            };
+        issues
+    }
 
-            out.write_fmt(format_args!("{}: ", instruction_name))?;
+    /// Every state or other instruction in a parsed script that can have a jump table entry, in script order.
+    /// Entries inside of raw data are included as they are
+    pub fn jump_table_states(&self, program: &[LocatedInstruction]) -> Vec<JumpEntry> {
+        let mut states = Vec::new();
 
-            let mut args = instruction.args.iter().peekable();
-            while let Some(arg) = args.next() {
-                out.write_fmt(format_args!("{}", arg_to_string(self, arg)?))?;
+        for located in program {
+            let args = &located.instruction.args;
 
-                if args.peek().is_some() {
-                    out.write_fmt(format_args!(", "))?;
+            if located.instruction.identifier == InstructionIdentifier::RawData {
+                for entry in args.get(1..).unwrap_or_default().chunks_exact(3) {
+                    if let [ArgValue::Number(table_id), ArgValue::String32(name), ArgValue::Number(relative_offset)] =
+                        entry
+                    {
+                        states.push(JumpEntry {
+                            table_id: *table_id as u32,
+                            name: name.clone(),
+                            offset: (located.offset + *relative_offset as usize) as u32,
+                        });
+                    }
                 }
+
+                continue;
             }
 
-            out.write_char('\n')?;
+            let Ok(info) = self.get_by_identifier(&located.instruction.identifier) else {
+                continue;
+            };
 
-            match instruction_info.block_type() {
-                CodeBlock::BeginNonrecursive | CodeBlock::Begin => {
-                    indent += 1;
-                    last_block_type = instruction_info.name();
-                    last_block_type_valid = true;
-                },
-                _ => {}
+            if let (true, Some(ArgValue::String32(name))) =
+                (self.is_jump_entry_id(info.id()), args.first())
+            {
+                states.push(JumpEntry {
+                    table_id: info.id(),
+                    name: name.clone(),
+                    offset: located.offset as u32,
+                });
             }
+        }
 
-            if block_ended {
-                out.write_char('\n')?;
-                block_ended = false;
-            }
+        states
+    }
+
+    /// Builds a [`InstructionIdentifier::JumpTable`] instruction holding the jump table of a script,
+    /// if the rebuilder would generate a different one from the parsed program
+    pub(crate) fn recorded_jump_table(
+        &self,
+        jump_table: &[JumpEntry],
+        program: &[LocatedInstruction],
+    ) -> Option<InstructionValue> {
+        let states = self.jump_table_states(program);
+
+        let key = |e: &JumpEntry| (e.table_id, e.name.0.clone(), e.offset);
+        let generated = self.generate_jump_table(states.clone());
+        if generated.iter().map(key).eq(jump_table.iter().map(key)) {
+            return None;
         }
 
-        Ok(out)
+        let mut args = SmallVec::new();
+        for entry in jump_table {
+            let index = states
+                .iter()
+                .filter(|s| s.table_id == entry.table_id && s.name.0 == entry.name.0)
+                .position(|s| s.offset == entry.offset);
+
+            let Some(index) = index else {
+                log::warn!(
+                    "jump table entry `{}` at offset {:#X} doesn't point at a state with the same name, it won't be rebuilt",
+                    entry.name,
+                    entry.offset
+                );
+                continue;
+            };
+
+            args.push(ArgValue::Number(entry.table_id as BBSNumber));
+            args.push(ArgValue::String32(entry.name.clone()));
+            args.push(ArgValue::Number(index as BBSNumber));
+        }
+
+        Some(InstructionValue {
+            identifier: InstructionIdentifier::JumpTable,
+            args,
+        })
     }
 
-    pub fn parse<B: ByteOrder>(
+    /// Same as [`ScriptConfig::parse`], but keeps the offset and size of every instruction
+    pub fn parse_located<B: ByteOrder>(
         &self,
         input: impl AsRef<[u8]>,
-    ) -> Result<Vec<InstructionValue>, BBScriptError> {
+    ) -> Result<Vec<LocatedInstruction>, BBScriptError> {
+        self.parse_located_with::<B>(input, ParseRecovery::Strict)
+    }
+
+    /// Same as [`ScriptConfig::parse_located`], using `recovery` to handle data that can't be decoded
+    pub fn parse_located_with<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        recovery: ParseRecovery,
+    ) -> Result<Vec<LocatedInstruction>, BBScriptError> {
+        let input = input.as_ref();
+        let (jump_table, base) = self.read_jump_table::<B>(input)?;
+
+        // parse the actual scripts
+        self.parse_script::<B>(&input[base..], base, recovery, &jump_table)
+    }
+
+    /// Reads the jump table at the start of a script.
+    /// Returns the entries in the order they are stored, along with the offset where the script data begins
+    pub fn read_jump_table<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<(Vec<JumpEntry>, usize), BBScriptError> {
         const JUMP_ENTRY_LENGTH: usize = 0x24;
 
-        let mut input = input.as_ref();
+        let mut input = Cursor::new(input.as_ref());
 
         // get jump table size in bytes
-        let jump_table_size: usize = JUMP_ENTRY_LENGTH
-            * self
-                .jump_table_ids
-                .iter()
-                .map(|_| input.read_u32::<B>().unwrap() as usize)
-                .sum::<usize>();
+        let mut jump_entry_counts = Vec::with_capacity(self.jump_table_ids.len());
+        for _ in &self.jump_table_ids {
+            let offset = input.position() as usize;
+            let count = input
+                .read_u32::<B>()
+                .map_err(|_| BBScriptError::JumpTableEof(offset))?;
+
+            jump_entry_counts.push(count as usize);
+        }
+        let jump_table_size = jump_entry_counts
+            .iter()
+            .fold(0usize, |sum, count| sum.saturating_add(*count))
+            .saturating_mul(JUMP_ENTRY_LENGTH);
 
         log::debug!("jump table size: {jump_table_size}");
 
-        if jump_table_size >= input.len() {
+        if jump_table_size >= input.remaining() {
             return Err(BBScriptError::IncorrectJumpTableSize(
                 jump_table_size.to_string(),
             ));
         }
 
-        input.advance(jump_table_size);
+        let mut jump_table = Vec::new();
+        for (table_id, count) in self.jump_table_ids.iter().zip(jump_entry_counts) {
+            for _ in 0..count {
+                let mut name = [0; ArgType::STRING32_SIZE];
+                input.read_exact(&mut name)?;
 
-        // parse the actual scripts
-        self.parse_script::<B>(input)
+                jump_table.push(JumpEntry {
+                    table_id: *table_id,
+                    name: SizedString(process_string_buf(&name)),
+                    offset: input.read_u32::<B>()?,
+                });
+            }
+        }
+
+        Ok((jump_table, input.position() as usize))
     }
 
-    fn parse_script<B: ByteOrder>(
+    /// Parses the script data following the jump table.
+    /// `base` is the offset of the script data within the input, used to report errors
+    pub(crate) fn parse_script<B: ByteOrder>(
         &self,
         bytes: impl AsRef<[u8]>,
-    ) -> Result<Vec<InstructionValue>, BBScriptError> {
+        base: usize,
+        recovery: ParseRecovery,
+        jump_table: &[JumpEntry],
+    ) -> Result<Vec<LocatedInstruction>, BBScriptError> {
         use crate::game_config::InstructionInfo;
 
-        let mut input = Cursor::new(bytes.as_ref());
-        let mut program = Vec::with_capacity(bytes.as_ref().len() / 2);
+        let bytes = bytes.as_ref();
+        let mut input = Cursor::new(bytes);
+        let mut program = Vec::with_capacity(bytes.len() / 2);
+
+        // sizes found for unknown instructions while resyncing
+        let mut inferred_sizes: HashMap<u32, usize> = HashMap::new();
 
-        match &self.instructions {
-            InstructionInfo::Sized(id_map) => {
-                while input.remaining() != 0 {
-                    program.push(self.parse_sized::<B>(id_map, &mut input)?);
+        let mut anchors: Vec<usize> = jump_table.iter().map(|e| e.offset as usize).collect();
+        anchors.sort_unstable();
+        anchors.dedup();
+
+        while input.remaining() != 0 {
+            let offset = input.position() as usize;
+
+            let instruction = match &self.instructions {
+                InstructionInfo::Sized(id_map) => self.parse_sized::<B>(id_map, &mut input, base),
+                InstructionInfo::Unsized(id_map) => {
+                    self.parse_unsized::<B>(id_map, &mut input, base)
                 }
+            };
 
-                Ok(program)
-            }
-            InstructionInfo::Unsized(id_map) => {
-                while input.remaining() != 0 {
-                    program.push(self.parse_unsized::<B>(id_map, &mut input)?);
+            let instruction = match (instruction, recovery) {
+                (Ok(instruction), _) => instruction,
+                (Err(e), ParseRecovery::Strict) => return Err(e),
+                (Err(BBScriptError::UnknownInstructionID(id)), ParseRecovery::Resync) => {
+                    let resync = match &self.instructions {
+                        InstructionInfo::Sized(id_map) => {
+                            let known_size = inferred_sizes.get(&id).copied();
+                            let depth = self.block_depth(&program);
+                            self.find_resync_offset::<B>(
+                                id_map, bytes, offset, depth, known_size, &anchors,
+                            )
+                        }
+                        InstructionInfo::Unsized(_) => None,
+                    };
+
+                    if let Some(next_offset) = resync {
+                        let size = next_offset - offset;
+                        log::warn!(
+                            "skipped unknown instruction {id} at offset {:#X} with inferred size {size}",
+                            base + offset
+                        );
+
+                        if let Some(previous) = inferred_sizes.insert(id, size) {
+                            if previous != size {
+                                log::warn!("instruction {id} was previously inferred to have size {previous}");
+                            }
+                        }
+
+                        let mut args = SmallVec::new();
+                        if size > 4 {
+                            args.push(ArgValue::Unknown(SmallVec::from_slice(
+                                &bytes[offset + 4..next_offset],
+                            )));
+                        }
+
+                        input.set_position(next_offset as u64);
+                        program.push(LocatedInstruction {
+                            offset,
+                            size,
+                            instruction: InstructionValue {
+                                identifier: InstructionIdentifier::Id(id),
+                                args,
+                            },
+                        });
+
+                        continue;
+                    }
+
+                    log::warn!(
+                        "could not resync after offset {:#X}, keeping the rest as raw data",
+                        base + offset
+                    );
+
+                    program.push(LocatedInstruction {
+                        offset,
+                        size: bytes.len() - offset,
+                        instruction: raw_data(&bytes[offset..], offset, jump_table),
+                    });
+
+                    break;
                 }
+                (Err(e), ParseRecovery::RawTail | ParseRecovery::Resync) => {
+                    log::warn!(
+                        "stopped decoding at offset {:#X}, keeping the rest as raw data: {e}",
+                        base + offset
+                    );
+
+                    program.push(LocatedInstruction {
+                        offset,
+                        size: bytes.len() - offset,
+                        instruction: raw_data(&bytes[offset..], offset, jump_table),
+                    });
 
-                Ok(program)
+                    break;
+                }
+            };
+
+            program.push(LocatedInstruction {
+                offset,
+                size: input.position() as usize - offset,
+                instruction,
+            });
+        }
+
+        Ok(program)
+    }
+
+    /// Finds where decoding can continue after an unknown instruction at `offset`.
+    ///
+    /// Candidates are tried every 4 bytes up to the next jump table entry.
+    /// A candidate is accepted if the instructions following it decode up to exactly the next entry
+    /// while keeping the blocks of the current state balanced,
+    /// or decode a run of [`RESYNC_RUN_LENGTH`] instructions when no entries are left.
+    /// If no candidate is accepted, the next entry itself is used
+    fn find_resync_offset<B: ByteOrder>(
+        &self,
+        id_map: &HashMap<u32, SizedInstruction>,
+        bytes: &[u8],
+        offset: usize,
+        depth: usize,
+        known_size: Option<usize>,
+        anchors: &[usize],
+    ) -> Option<usize> {
+        const ALIGNMENT: usize = 4;
+
+        let next_anchor = anchors.iter().copied().find(|a| *a > offset);
+        let end = next_anchor.unwrap_or(bytes.len());
+
+        // the unknown instruction might open a block itself, but only assume so if nothing else works
+        for depth in [depth, depth + 1] {
+            let candidates = known_size
+                .map(|size| offset + size)
+                .into_iter()
+                .chain((offset + ALIGNMENT..end).step_by(ALIGNMENT));
+
+            for candidate in candidates {
+                if candidate < end
+                    && self.decodes_cleanly::<B>(
+                        id_map,
+                        bytes,
+                        candidate,
+                        depth,
+                        next_anchor,
+                        anchors,
+                    )
+                {
+                    return Some(candidate);
+                }
             }
         }
+
+        // a jump table entry is always the start of a state, but an unaligned one or one leaving no room
+        // for the ID can't be rebuilt as an unknown instruction
+        next_anchor.filter(|anchor| anchor % ALIGNMENT == 0 && anchor - offset >= ALIGNMENT)
+    }
+
+    /// Checks if the instructions starting at `offset` decode cleanly until `target`,
+    /// or for [`RESYNC_RUN_LENGTH`] instructions if there is no target.
+    ///
+    /// `depth` is the block depth at `offset`, which must never drop below the state's own block before
+    /// it ends, and must be back at 0 when reaching `target`.
+    /// Jump table instructions found anywhere other than a jump table entry also count as a failure
+    fn decodes_cleanly<B: ByteOrder>(
+        &self,
+        id_map: &HashMap<u32, SizedInstruction>,
+        bytes: &[u8],
+        offset: usize,
+        mut depth: usize,
+        target: Option<usize>,
+        anchors: &[usize],
+    ) -> bool {
+        let mut position = offset;
+        let mut decoded = 0;
+
+        loop {
+            match target {
+                Some(target) if position >= target => return position == target && depth == 0,
+                None if decoded >= RESYNC_RUN_LENGTH || position == bytes.len() => return true,
+                _ => {}
+            }
+
+            let Some(id) = bytes.get(position..position + 4).map(B::read_u32) else {
+                return false;
+            };
+
+            if self.is_jump_entry_id(id) && anchors.binary_search(&position).is_err() {
+                return false;
+            }
+
+            let Some(instruction) = id_map.get(&id) else {
+                return false;
+            };
+
+            match instruction.code_block {
+                CodeBlock::Begin => depth += 1,
+                CodeBlock::End if depth > 1 => depth -= 1,
+                CodeBlock::EndState if depth > 0 => depth = 0,
+                CodeBlock::End | CodeBlock::EndState => return false,
+                _ => {}
+            }
+
+            position += instruction.size.max(4);
+            decoded += 1;
+        }
+    }
+
+    /// Block depth at the end of `program`, counted from the start of the last state
+    fn block_depth(&self, program: &[LocatedInstruction]) -> usize {
+        let mut depth: usize = 0;
+
+        for located in program {
+            let Ok(info) = self.get_by_identifier(&located.instruction.identifier) else {
+                continue;
+            };
+
+            match info.block_type() {
+                CodeBlock::Begin => depth += 1,
+                CodeBlock::End => depth = depth.saturating_sub(1),
+                CodeBlock::EndState => depth = 0,
+                _ => {}
+            }
+        }
+
+        depth
     }
 
     fn parse_sized<B: ByteOrder>(
         &self,
         id_map: &HashMap<u32, SizedInstruction>,
         input: &mut Cursor<&[u8]>,
+        base: usize,
     ) -> Result<InstructionValue, BBScriptError> {
-        let instruction_id = input.read_u32::<B>()?;
+        let offset = base + input.position() as usize;
+
+        let instruction_id = input
+            .read_u32::<B>()
+            .map_err(|_| BBScriptError::InstructionEof(offset))?;
 
         let instruction = id_map
             .get(&instruction_id)
@@ -234,8 +1031,11 @@ impl ScriptConfig {
         let args = instruction
             .args()
             .into_iter()
-            .map(|arg_type| self.parse_argument::<B>(arg_type, input))
-            .collect();
+            .enumerate()
+            .map(|(index, arg_type)| {
+                self.parse_argument::<B>(arg_type, input, base, instruction_id, index)
+            })
+            .collect::<Result<_, _>>()?;
 
         let instruction = InstructionValue {
             identifier: instruction_identifier,
@@ -251,11 +1051,18 @@ impl ScriptConfig {
         &self,
         id_map: &HashMap<u32, UnsizedInstruction>,
         input: &mut Cursor<&[u8]>,
+        base: usize,
     ) -> Result<InstructionValue, BBScriptError> {
         log::debug!("offset {:#X} from end of file", input.remaining());
 
-        let instruction_id = input.read_u32::<B>().unwrap();
-        let instruction_size = input.read_u32::<B>().unwrap();
+        let offset = base + input.position() as usize;
+
+        let instruction_id = input
+            .read_u32::<B>()
+            .map_err(|_| BBScriptError::InstructionEof(offset))?;
+        let instruction_size = input
+            .read_u32::<B>()
+            .map_err(|_| BBScriptError::InstructionSizeEof(offset, instruction_id))?;
 
         log::info!(
             "finding info for instruction with ID {instruction_id} and size {instruction_size}"
@@ -276,9 +1083,17 @@ impl ScriptConfig {
 
         let args = instruction
             .args_with_known_size(instruction_size as usize)
+            .ok_or(BBScriptError::InstructionSizeTooSmall(
+                offset,
+                instruction_id,
+                instruction_size as usize,
+            ))?
             .into_iter()
-            .map(|arg_type| self.parse_argument::<B>(arg_type, input))
-            .collect();
+            .enumerate()
+            .map(|(index, arg_type)| {
+                self.parse_argument::<B>(arg_type, input, base, instruction_id, index)
+            })
+            .collect::<Result<_, _>>()?;
 
         let instruction = InstructionValue {
             identifier: instruction_identifier,
@@ -293,45 +1108,79 @@ impl ScriptConfig {
         &self,
         arg_type: ArgType,
         input: &mut Cursor<&[u8]>,
-    ) -> ArgValue {
-        match arg_type {
+        base: usize,
+        instruction_id: u32,
+        index: usize,
+    ) -> Result<ArgValue, BBScriptError> {
+        let offset = base + input.position() as usize;
+        let eof = |_| BBScriptError::ArgumentEof(offset, instruction_id, index);
+
+        // check up front so huge unknown sizes don't allocate before failing
+        if arg_type.size() > input.remaining() {
+            return Err(BBScriptError::ArgumentEof(offset, instruction_id, index));
+        }
+
+        let value = match arg_type {
             // get SmallVec of bytes
             ArgType::Unknown(n) => {
-                ArgValue::Unknown((0..n).map(|_| input.read_u8().unwrap()).collect())
+                let mut buf = SmallVec::from_elem(0, n);
+                input.read_exact(&mut buf).map_err(eof)?;
+
+                ArgValue::Unknown(buf)
             }
             ArgType::String16 => {
                 let mut buf = [0; ArgType::STRING16_SIZE];
-                input.copy_to_slice(&mut buf);
+                input.read_exact(&mut buf).map_err(eof)?;
 
                 ArgValue::String16(SizedString(process_string_buf(&buf)))
             }
             ArgType::String32 => {
                 let mut buf = [0; ArgType::STRING32_SIZE];
-                input.copy_to_slice(&mut buf);
+                input.read_exact(&mut buf).map_err(eof)?;
 
                 ArgValue::String32(SizedString(process_string_buf(&buf)))
             }
-            ArgType::Number => ArgValue::Number(input.read_i32::<B>().unwrap()),
-            ArgType::Enum(s) => ArgValue::Enum(s.clone(), input.read_i32::<B>().unwrap()),
+            ArgType::Number => ArgValue::Number(input.read_i32::<B>().map_err(eof)?),
+            ArgType::Enum(s) => ArgValue::Enum(s.clone(), input.read_i32::<B>().map_err(eof)?),
             ArgType::AccessedValue => {
-                let tag = input.read_i32::<B>().unwrap();
+                let tag = input.read_i32::<B>().map_err(eof)?;
+                let value = input.read_i32::<B>().map_err(eof)?;
 
                 if tag == self.literal_tag {
-                    ArgValue::AccessedValue(TaggedValue::Literal(input.read_i32::<B>().unwrap()))
+                    ArgValue::AccessedValue(TaggedValue::Literal(value))
                 } else if tag == self.variable_tag {
-                    ArgValue::AccessedValue(TaggedValue::Variable(input.read_i32::<B>().unwrap()))
+                    ArgValue::AccessedValue(TaggedValue::Variable(value))
                 } else {
                     log::warn!(
                         "found improperly tagged AccessedValue, most likely just two Numbers"
                     );
-                    ArgValue::AccessedValue(TaggedValue::Improper {
-                        tag,
-                        value: input.read_i32::<B>().unwrap(),
-                    })
+                    ArgValue::AccessedValue(TaggedValue::Improper { tag, value })
                 }
             }
+        };
+
+        Ok(value)
+    }
+}
+
+/// Builds a [`InstructionIdentifier::RawData`] instruction from the data starting at `offset`
+fn raw_data(data: &[u8], offset: usize, jump_table: &[JumpEntry]) -> InstructionValue {
+    let mut args = SmallVec::new();
+    args.push(ArgValue::Unknown(SmallVec::from_slice(data)));
+
+    for entry in jump_table {
+        let relative_offset = entry.offset as usize;
+        if (offset..offset + data.len()).contains(&relative_offset) {
+            args.push(ArgValue::Number(entry.table_id as BBSNumber));
+            args.push(ArgValue::String32(entry.name.clone()));
+            args.push(ArgValue::Number((relative_offset - offset) as BBSNumber));
         }
     }
+
+    InstructionValue {
+        identifier: InstructionIdentifier::RawData,
+        args,
+    }
 }
 
 fn process_string_buf(buf: &[u8]) -> String {
@@ -339,5 +1188,236 @@ fn process_string_buf(buf: &[u8]) -> String {
         .filter(|x| **x != 0)
         .map(|x| *x as char)
         .collect::<String>()
-        .replace('\'', r"\'")
+}
+
+pub(crate) fn escaped(string: &str) -> String {
+    string.replace('\'', r"\'")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::BBScriptError;
+    use crate::fixture::bytes;
+    use crate::parser::{
+        InstructionIdentifier, InstructionValue, JumpTableProblem, LocatedInstruction,
+        ParseRecovery,
+    };
+    use crate::{ScriptConfig, ScriptHeader, SupportedGame};
+    use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  ifOperation: (IS_GREATER), Mem(Tmp), Val(2)
+    exitState:
+  endIf:
+endState:
+";
+
+    #[test]
+    fn raw_tail_round_trip() {
+        let config = SupportedGame::Ggst.into_config();
+        let script = format!("{SCRIPT}beginState: s32'CmnActCrouch'\n  exitState:\nendState:\n");
+        let mut input = bytes(&script);
+
+        // replace `exitState` in the first state with an ID missing from the config
+        let exit_offset = 0x4 + 0x24 * 2 + 0x24 + 0x28 + 0x18;
+        LittleEndian::write_u32(&mut input[exit_offset..], 0xFFFFFF);
+
+        assert!(matches!(
+            config.parse::<LittleEndian>(&input),
+            Err(BBScriptError::UnknownInstructionID(0xFFFFFF))
+        ));
+
+        let readable = config
+            .parse_to_string_with::<LittleEndian>(&input, 12, ParseRecovery::RawTail)
+            .unwrap();
+        assert!(readable.contains("#raw: 0xFFFFFF00"));
+        assert!(readable.contains("s32'CmnActCrouch'"));
+
+        let rebuilt = bytes(&readable);
+        assert_eq!(input, rebuilt);
+    }
+
+    #[test]
+    fn resync_round_trip() {
+        let config = SupportedGame::Ggst.into_config();
+        let script = format!(
+            "{SCRIPT}beginState: s32'CmnActCrouch'\n  sprite: s32'nmc001_00', 3\nendState:\n"
+        );
+        let mut input = bytes(&script);
+
+        // replace both `sprite` instructions with an ID missing from the config
+        let first_sprite = 0x4 + 0x24 * 2 + 0x24;
+        let second_sprite = first_sprite + 0x28 + 0x18 + 0x4 * 3 + 0x24;
+        LittleEndian::write_u32(&mut input[first_sprite..], 0xFFFFFF);
+        LittleEndian::write_u32(&mut input[second_sprite..], 0xFFFFFF);
+
+        let program = config
+            .parse_located_with::<LittleEndian>(&input, ParseRecovery::Resync)
+            .unwrap();
+        let unknown: Vec<_> = program
+            .iter()
+            .filter(|i| i.instruction.identifier == InstructionIdentifier::Id(0xFFFFFF))
+            .collect();
+
+        assert_eq!(unknown.len(), 2);
+        assert!(unknown.iter().all(|i| i.size == 0x28));
+
+        let readable = config
+            .parse_to_string_with::<LittleEndian>(&input, 12, ParseRecovery::Resync)
+            .unwrap();
+        assert!(readable.contains("// inferred size: 40"));
+        assert!(!readable.contains("#raw"));
+
+        let rebuilt = bytes(&readable);
+        assert_eq!(input, rebuilt);
+    }
+
+    #[test]
+    fn resync_keeps_unaligned_anchor_as_raw() {
+        let config = SupportedGame::Ggst.into_config();
+        let script = format!("{SCRIPT}beginState: s32'CmnActCrouch'\nendState:\n");
+        let mut input = bytes(&script);
+
+        // the `endState` before the second state is unknown, and its jump table entry points 2 bytes into it
+        let base = 0x4 + 0x24 * 2;
+        let program = config.parse_located::<LittleEndian>(&input).unwrap();
+        let crouch = program[program.len() - 2].offset;
+        LittleEndian::write_u32(&mut input[base + crouch - 4..], 0xFFFFFF);
+        LittleEndian::write_u32(&mut input[0x4 + 0x24 + 0x20..], (crouch - 2) as u32);
+
+        let readable = config
+            .parse_to_string_with::<LittleEndian>(&input, 12, ParseRecovery::Resync)
+            .unwrap();
+        assert!(readable.contains("#raw"), "{readable}");
+        assert!(!readable.contains("inferred size"), "{readable}");
+
+        let rebuilt = bytes(&readable);
+        assert_eq!(input, rebuilt);
+    }
+
+    /// Small xorshift generator so the inputs are the same on every run
+    fn random_bytes(seed: &mut u64, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                *seed as u8
+            })
+            .collect()
+    }
+
+    fn parse_everything<B: ByteOrder>(configs: &[ScriptConfig], input: &[u8]) {
+        // the default header hashes the whole config, which is too slow to do for every input
+        let header = ScriptHeader::default();
+        for recovery in [
+            ParseRecovery::Strict,
+            ParseRecovery::RawTail,
+            ParseRecovery::Resync,
+        ] {
+            for config in configs {
+                let _ = config.parse_to_string_with_header::<B>(input, 12, recovery, &header);
+            }
+        }
+        for config in configs {
+            let _ = config.parse_tree::<B>(input);
+        }
+    }
+
+    #[test]
+    fn truncated_script_errors() {
+        let configs: Vec<_> = SupportedGame::ALL.map(|g| g.into_config()).into();
+        let config = SupportedGame::Ggst.into_config();
+        let script = bytes(SCRIPT);
+
+        // cut off partway through the number argument of `sprite`
+        let sprite_offset = 0x4 + 0x24 + 0x24;
+        let number_offset = sprite_offset + 0x4 + 0x20;
+        let error = config
+            .parse::<LittleEndian>(&script[..number_offset + 2])
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            BBScriptError::ArgumentEof(offset, 2, 1) if offset == number_offset
+        ));
+
+        for len in 0..script.len() {
+            parse_everything::<LittleEndian>(&configs, &script[..len]);
+        }
+    }
+
+    #[test]
+    fn jump_table_issues() {
+        let config = SupportedGame::Ggst.into_config();
+        let script = format!("{SCRIPT}beginState: s32'CmnActCrouch'\nendState:\n");
+        let input = bytes(&script);
+
+        let problems = |input: &[u8]| {
+            config
+                .parse_structured_with::<LittleEndian>(input, ParseRecovery::Strict)
+                .unwrap()
+                .jump_table_issues
+                .into_iter()
+                .map(|issue| issue.problem)
+                .collect::<Vec<_>>()
+        };
+
+        let structured = config
+            .parse_structured_with::<LittleEndian>(&input, ParseRecovery::Strict)
+            .unwrap();
+        assert_eq!(structured.jump_table.len(), 2);
+        assert!(structured.jump_table_issues.is_empty());
+
+        // the second entry points at `sprite`, then inside of it
+        let (second_offset, sprite_offset) = (0x4 + 0x24 + 0x20, 0x24);
+        let mut orphan = input.clone();
+        LittleEndian::write_u32(&mut orphan[second_offset..], sprite_offset);
+        assert!(matches!(problems(&orphan)[..], [JumpTableProblem::Orphan]));
+
+        let mut mid_instruction = input.clone();
+        LittleEndian::write_u32(&mut mid_instruction[second_offset..], sprite_offset + 4);
+        assert!(matches!(
+            problems(&mid_instruction)[..],
+            [JumpTableProblem::MidInstruction(0x24)]
+        ));
+
+        let mut mismatch = input.clone();
+        mismatch[0x4..0x4 + 4].copy_from_slice(b"Oops");
+        assert!(matches!(
+            &problems(&mismatch)[..],
+            [JumpTableProblem::NameMismatch(name)] if name == "CmnActStand"
+        ));
+
+        // raw data built by callers may not have any args
+        let raw = LocatedInstruction {
+            offset: 0,
+            size: 0,
+            instruction: InstructionValue {
+                identifier: InstructionIdentifier::RawData,
+                args: Default::default(),
+            },
+        };
+        assert!(config.jump_table_states(&[raw]).is_empty());
+    }
+
+    #[test]
+    fn arbitrary_input_does_not_panic() {
+        let configs: Vec<_> = SupportedGame::ALL.map(|g| g.into_config()).into();
+        let mut seed = 0x2545F4914F6CDD1D;
+
+        for len in (0..512).step_by(7) {
+            let mut input = random_bytes(&mut seed, len);
+            parse_everything::<LittleEndian>(&configs, &input);
+            parse_everything::<BigEndian>(&configs, &input);
+
+            // a plausible jump table count gets past the header more often
+            if input.len() >= 4 {
+                input[..4].copy_from_slice(&[0, 0, 0, 0]);
+                parse_everything::<LittleEndian>(&configs, &input);
+            }
+        }
+    }
 }
@@ -0,0 +1,391 @@
+use std::collections::HashSet;
+
+use byteorder::ByteOrder;
+
+use crate::ast::Script;
+use crate::diff::{lines, Line};
+use crate::error::BBScriptError;
+use crate::fnv1a_hash;
+use crate::game_config::ScriptConfig;
+use crate::parser::escaped;
+use crate::states::{first_named_roots, render, ReadableRoots};
+
+const ADD_DIRECTIVE: &str = "#add:";
+const REPLACE_DIRECTIVE: &str = "#replace:";
+const DELETE_DIRECTIVE: &str = "#delete:";
+
+/// Changes to the states and subroutines of a script, keyed by name so they can be applied to other versions of it.
+///
+/// Patch files are readable scripts split into sections by a directive line for each change:
+/// `#add: s32'Name'` optionally followed by `s32'Previous'`, the state to insert it after,
+/// `#replace: s32'Name', 0x<hash>` and `#delete: s32'Name', 0x<hash>`, where the hash is of the state the patch was made against.
+/// Added and replaced states follow their directive in the readable format
+#[derive(Debug, Clone, Default)]
+pub struct StatePatch {
+    pub changes: Vec<StateChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateChange {
+    Add {
+        name: String,
+        after: Option<String>,
+        body: String,
+    },
+    Replace {
+        name: String,
+        original: u64,
+        body: String,
+    },
+    Delete {
+        name: String,
+        original: u64,
+    },
+}
+
+impl StatePatch {
+    /// Records the changes from `base` to `modded`
+    pub fn new(
+        config: &ScriptConfig,
+        base: &Script,
+        modded: &Script,
+    ) -> Result<Self, BBScriptError> {
+        let base_roots = first_named_roots(base);
+        let modded_roots = first_named_roots(modded);
+
+        let mut patch = Self::default();
+        let mut previous: Option<&str> = None;
+
+        for (name, modded_id) in &modded_roots {
+            match base_roots.iter().find(|(n, _)| n == name) {
+                None => patch.changes.push(StateChange::Add {
+                    name: name.clone(),
+                    after: previous.map(String::from),
+                    body: render(config, modded, *modded_id)?,
+                }),
+                Some((_, base_id)) => {
+                    let original = lines(config, base, *base_id)?;
+                    if original != lines(config, modded, *modded_id)? {
+                        patch.changes.push(StateChange::Replace {
+                            name: name.clone(),
+                            original: state_hash(&original),
+                            body: render(config, modded, *modded_id)?,
+                        });
+                    }
+                }
+            }
+
+            previous = Some(name);
+        }
+
+        for (name, base_id) in &base_roots {
+            if !modded_roots.iter().any(|(n, _)| n == name) {
+                patch.changes.push(StateChange::Delete {
+                    name: name.clone(),
+                    original: state_hash(&lines(config, base, *base_id)?),
+                });
+            }
+        }
+
+        Ok(patch)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, BBScriptError> {
+        let mut patch = Self::default();
+        let mut body: Option<&mut String> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let invalid =
+                |reason: &str| BBScriptError::InvalidPatch(format!("line {}: {reason}", index + 1));
+
+            let directive = [ADD_DIRECTIVE, REPLACE_DIRECTIVE, DELETE_DIRECTIVE]
+                .into_iter()
+                .find_map(|d| Some((d, line.trim().strip_prefix(d)?)));
+
+            let Some((directive, args)) = directive else {
+                match body.as_deref_mut() {
+                    Some(body) => {
+                        body.push_str(line);
+                        body.push('\n');
+                    }
+                    None if line.trim().is_empty() || line.trim().starts_with("//") => {}
+                    None => {
+                        return Err(invalid(
+                            "instructions outside of an added or replaced state",
+                        ))
+                    }
+                }
+                continue;
+            };
+
+            let (name, rest) =
+                parse_name(args).ok_or_else(|| invalid("expected a s32 state name"))?;
+            let rest = rest.trim();
+
+            let change = match directive {
+                ADD_DIRECTIVE => StateChange::Add {
+                    name,
+                    after: match rest.strip_prefix(',') {
+                        Some(rest) => Some(
+                            parse_name(rest)
+                                .ok_or_else(|| invalid("expected a s32 state name"))?
+                                .0,
+                        ),
+                        None => None,
+                    },
+                    body: String::new(),
+                },
+                _ => {
+                    let original = rest
+                        .strip_prefix(',')
+                        .and_then(|hash| hash.trim().strip_prefix("0x"))
+                        .and_then(|hash| u64::from_str_radix(hash.trim(), 16).ok())
+                        .ok_or_else(|| invalid("expected the hash of the original state"))?;
+
+                    if directive == REPLACE_DIRECTIVE {
+                        StateChange::Replace {
+                            name,
+                            original,
+                            body: String::new(),
+                        }
+                    } else {
+                        StateChange::Delete { name, original }
+                    }
+                }
+            };
+
+            patch.changes.push(change);
+            body = match patch.changes.last_mut() {
+                Some(StateChange::Add { body, .. } | StateChange::Replace { body, .. }) => {
+                    Some(body)
+                }
+                _ => None,
+            };
+        }
+
+        Ok(patch)
+    }
+}
+
+impl std::fmt::Display for StatePatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            match change {
+                StateChange::Add { name, after, body } => {
+                    write!(f, "{ADD_DIRECTIVE} s32'{}'", escaped(name))?;
+                    if let Some(after) = after {
+                        write!(f, ", s32'{}'", escaped(after))?;
+                    }
+                    write!(f, "\n{body}")?;
+                }
+                StateChange::Replace {
+                    name,
+                    original,
+                    body,
+                } => write!(
+                    f,
+                    "{REPLACE_DIRECTIVE} s32'{}', 0x{original:016X}\n{body}",
+                    escaped(name)
+                )?,
+                StateChange::Delete { name, original } => writeln!(
+                    f,
+                    "{DELETE_DIRECTIVE} s32'{}', 0x{original:016X}\n",
+                    escaped(name)
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ScriptConfig {
+    /// Applies a patch to a binary script, failing if any replaced or deleted state isn't the one the patch was made against
+    pub fn apply_patch<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        patch: &StatePatch,
+    ) -> Result<Vec<u8>, BBScriptError> {
+        let input = input.as_ref();
+        let script = self.parse_tree::<B>(input)?;
+        let named = first_named_roots(&script);
+        let mut roots = ReadableRoots::new(self, &script)?;
+
+        let check = |name: &str, original: u64| {
+            let (_, id) = named
+                .iter()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| BBScriptError::StateNotFound(name.into()))?;
+
+            match state_hash(&lines(self, &script, *id)?) == original {
+                true => Ok(()),
+                false => Err(BBScriptError::PatchConflict(name.into())),
+            }
+        };
+
+        let mut changed = HashSet::new();
+        for change in &patch.changes {
+            let (StateChange::Add { name, .. }
+            | StateChange::Replace { name, .. }
+            | StateChange::Delete { name, .. }) = change;
+            if !changed.insert(name) {
+                return Err(BBScriptError::InvalidPatch(format!(
+                    "state `{name}` is changed more than once"
+                )));
+            }
+
+            let position = |roots: &ReadableRoots, name: &str| {
+                roots
+                    .position(name)
+                    .ok_or_else(|| BBScriptError::StateNotFound(name.into()))
+            };
+
+            match change {
+                StateChange::Add { name, after, body } => {
+                    if roots.position(name).is_some() {
+                        return Err(BBScriptError::InvalidPatch(format!(
+                            "added state `{name}` already exists"
+                        )));
+                    }
+
+                    let index = match after {
+                        Some(after) => roots.position(after).map_or(roots.roots.len(), |i| i + 1),
+                        None => 0,
+                    };
+                    roots
+                        .roots
+                        .insert(index, (Some(name.clone()), body.clone()));
+                }
+                StateChange::Replace {
+                    name,
+                    original,
+                    body,
+                } => {
+                    check(name, *original)?;
+                    let index = position(&roots, name)?;
+                    roots.roots[index].1 = body.clone();
+                }
+                StateChange::Delete { name, original } => {
+                    check(name, *original)?;
+                    let index = position(&roots, name)?;
+                    roots.roots.remove(index);
+                }
+            }
+        }
+
+        roots.assemble::<B>(self)
+    }
+}
+
+/// FNV-1a hash of the readable form of a state, which stays the same across versions of the tool
+fn state_hash(lines: &[Line]) -> u64 {
+    fnv1a_hash(
+        lines
+            .iter()
+            .flat_map(|line| line.text().into_bytes().into_iter().chain([b'\n'])),
+    )
+}
+
+/// Parses a `s32'...'` string at the start of `input`, returning it and the rest of the input
+fn parse_name(input: &str) -> Option<(String, &str)> {
+    let input = input.trim_start().strip_prefix("s32'")?;
+
+    let mut escaped = false;
+    for (index, c) in input.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '\'' if !escaped => {
+                return Some((input[..index].replace(r"\'", "'"), &input[index + 1..]));
+            }
+            _ => escaped = false,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::BBScriptError;
+    use crate::fixture::bytes;
+    use crate::patch::StatePatch;
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    const BASE: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+endState:
+beginState: s32'CmnActJump'
+endState:
+";
+
+    const MODDED: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 8
+endState:
+beginState: s32'CmnActDash'
+  addPositionX: 1000, 0
+endState:
+beginState: s32'CmnActCrouch'
+  sprite: s32'nmc001_00', 3
+endState:
+";
+
+    #[test]
+    fn patch_newer_version() {
+        let config = SupportedGame::Ggst.into_config();
+        let tree = |source: &str| config.parse_tree::<LittleEndian>(bytes(source)).unwrap();
+
+        let patch = StatePatch::new(&config, &tree(BASE), &tree(MODDED)).unwrap();
+        assert_eq!(patch.changes.len(), 3);
+
+        let patch = StatePatch::parse(&patch.to_string()).unwrap();
+
+        // the game update changed a state the patch doesn't touch
+        let updated = BASE.replace("'nmc001_00', 3", "'nmc001_00', 4");
+        let patched = config
+            .apply_patch::<LittleEndian>(bytes(&updated), &patch)
+            .unwrap();
+        assert_eq!(
+            patched,
+            bytes(&MODDED.replace("'nmc001_00', 3", "'nmc001_00', 4"))
+        );
+
+        // but changing a replaced state makes the patch fail
+        let updated = BASE.replace("'nmc000_00', 5", "'nmc000_00', 6");
+        assert!(matches!(
+            config.apply_patch::<LittleEndian>(bytes(&updated), &patch),
+            Err(BBScriptError::PatchConflict(name)) if name == "CmnActStand"
+        ));
+    }
+
+    #[test]
+    fn patch_changing_a_state_twice() {
+        let config = SupportedGame::Ggst.into_config();
+        let tree = |source: &str| config.parse_tree::<LittleEndian>(bytes(source)).unwrap();
+
+        let without_crouch = BASE.replace(
+            "beginState: s32'CmnActCrouch'\n  sprite: s32'nmc001_00', 3\nendState:\n",
+            "",
+        );
+        let deleted = StatePatch::new(&config, &tree(BASE), &tree(&without_crouch))
+            .unwrap()
+            .to_string();
+        let hash = deleted.split(", ").nth(1).unwrap().trim();
+
+        for twice in [
+            format!("{deleted}{deleted}"),
+            format!("{deleted}#replace: s32'CmnActCrouch', {hash}\nendState:\n"),
+        ] {
+            let patch = StatePatch::parse(&twice).unwrap();
+            assert!(matches!(
+                config.apply_patch::<LittleEndian>(bytes(BASE), &patch),
+                Err(BBScriptError::InvalidPatch(_))
+            ));
+        }
+    }
+}
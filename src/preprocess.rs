@@ -0,0 +1,535 @@
+use std::path::{Path, PathBuf};
+
+use pest::error::{ErrorVariant, LineColLocation};
+
+use crate::error::BBScriptError;
+use crate::rebuilder::Rule;
+use crate::HashMap;
+
+pub const INCLUDE_DIRECTIVE: &str = "#include";
+pub const MACRO_DIRECTIVE: &str = "#macro";
+pub const END_MACRO_DIRECTIVE: &str = "#endmacro";
+/// Starts a line that expands a macro, as in `@name(arg, ...)`
+pub const MACRO_CALL: char = '@';
+
+/// Directives and macro calls are replaced, so a `/* */` comment they start would lose its opening
+const UNCLOSED_COMMENT: &str = "`/*` comments can't continue past a directive or macro call";
+
+/// How deep macros can expand other macros, so a macro that expands itself is an error instead of a hang
+const MAX_MACRO_DEPTH: usize = 32;
+
+/// A script with its includes and macros expanded, remembering where each line was written
+pub(crate) struct Expanded {
+    pub(crate) text: String,
+    lines: Vec<Origin>,
+    sources: Vec<Source>,
+}
+
+struct Source {
+    /// `None` for a script that wasn't read from a file
+    name: Option<String>,
+    text: String,
+}
+
+/// A line in one of the sources, counting from 1
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    source: usize,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Origin {
+    written: Location,
+    /// The line that expanded the macro this line is part of
+    expanded_at: Option<Location>,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<(Location, String)>,
+}
+
+/// Expands `script`, resolving includes relative to `path`. Scripts that weren't read from a file can't include files
+pub(crate) fn expand(script: &str, path: Option<&Path>) -> Result<Expanded, BBScriptError> {
+    let mut expander = Expander {
+        expanded: Expanded {
+            text: String::with_capacity(script.len()),
+            lines: Vec::new(),
+            sources: vec![Source {
+                name: path.map(|p| p.display().to_string()),
+                text: script.to_string(),
+            }],
+        },
+        macros: HashMap::new(),
+        includes: path.map(canonical).into_iter().collect(),
+    };
+
+    let dir = path.map(|p| p.parent().unwrap_or(Path::new(".")));
+    expander.source(0, dir)?;
+
+    Ok(expander.expanded)
+}
+
+struct Expander {
+    expanded: Expanded,
+    macros: HashMap<String, Macro>,
+    /// Files currently being included, to catch files that include themselves
+    includes: Vec<PathBuf>,
+}
+
+impl Expander {
+    fn source(&mut self, source: usize, dir: Option<&Path>) -> Result<(), BBScriptError> {
+        let text = std::mem::take(&mut self.expanded.sources[source].text);
+        let result = self.lines(source, &text, dir);
+        self.expanded.sources[source].text = text;
+
+        result
+    }
+
+    fn lines(
+        &mut self,
+        source: usize,
+        text: &str,
+        dir: Option<&Path>,
+    ) -> Result<(), BBScriptError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| {
+            (
+                Location {
+                    source,
+                    line: i + 1,
+                },
+                line,
+            )
+        });
+
+        let mut in_comment = false;
+        while let Some((at, line)) = lines.next() {
+            let code = strip_comments(line, &mut in_comment);
+            let trimmed = code.as_str();
+            if in_comment && is_expanded(trimmed) {
+                return Err(self.error(at, UNCLOSED_COMMENT));
+            }
+
+            if let Some(file) = directive_args(trimmed, INCLUDE_DIRECTIVE) {
+                let file = file
+                    .strip_prefix('"')
+                    .and_then(|f| f.strip_suffix('"'))
+                    .ok_or_else(|| self.error(at, "`#include` takes a path in double quotes"))?;
+                let dir = dir.ok_or_else(|| {
+                    self.error(
+                        at,
+                        "`#include` needs the path of the script to resolve files",
+                    )
+                })?;
+                self.include(dir.join(file), at)?;
+            } else if let Some(signature) = directive_args(trimmed, MACRO_DIRECTIVE) {
+                let (name, params) = split_call(signature)
+                    .filter(|(_, params)| params.iter().all(|p| is_name(p)))
+                    .ok_or_else(|| {
+                        self.error(at, "`#macro` takes a name and optionally (parameter, ...)")
+                    })?;
+                if self.macros.contains_key(name) {
+                    return Err(self.error(at, &format!("macro `{name}` is already defined")));
+                }
+
+                let mut body = Vec::new();
+                loop {
+                    let Some((body_at, body_line)) = lines.next() else {
+                        return Err(self.error(at, "`#macro` without `#endmacro`"));
+                    };
+                    let trimmed = strip_comments(body_line, &mut in_comment);
+                    if directive_args(&trimmed, END_MACRO_DIRECTIVE).is_some() {
+                        if in_comment {
+                            return Err(self.error(body_at, UNCLOSED_COMMENT));
+                        }
+                        break;
+                    }
+                    if directive_args(&trimmed, MACRO_DIRECTIVE).is_some()
+                        || directive_args(&trimmed, INCLUDE_DIRECTIVE).is_some()
+                    {
+                        return Err(
+                            self.error(body_at, "macros can't define macros or include files")
+                        );
+                    }
+                    body.push((body_at, body_line.to_string()));
+                }
+
+                let params = params.into_iter().map(str::to_string).collect();
+                self.macros.insert(name.to_string(), Macro { params, body });
+            } else if directive_args(trimmed, END_MACRO_DIRECTIVE).is_some() {
+                return Err(self.error(at, "`#endmacro` without `#macro`"));
+            } else if let Some(call) = trimmed.strip_prefix(MACRO_CALL) {
+                self.call(call, at, 0)?;
+            } else {
+                self.push(
+                    line,
+                    Origin {
+                        written: at,
+                        expanded_at: None,
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn include(&mut self, path: PathBuf, at: Location) -> Result<(), BBScriptError> {
+        let canonical = canonical(&path);
+        if self.includes.contains(&canonical) {
+            return Err(self.error(at, &format!("`{}` includes itself", path.display())));
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| self.error(at, &format!("can't read `{}`: {e}", path.display())))?;
+
+        self.expanded.sources.push(Source {
+            name: Some(path.display().to_string()),
+            text,
+        });
+        self.includes.push(canonical);
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        self.source(self.expanded.sources.len() - 1, Some(dir))?;
+        self.includes.pop();
+
+        Ok(())
+    }
+
+    /// Expands `@call`, written at `at`
+    fn call(&mut self, call: &str, at: Location, depth: usize) -> Result<(), BBScriptError> {
+        let (name, args) =
+            split_call(call).ok_or_else(|| self.error(at, "expected @name or @name(arg, ...)"))?;
+        if depth == MAX_MACRO_DEPTH {
+            return Err(self.error(
+                at,
+                &format!("macros nested too deeply, `{name}` may expand itself"),
+            ));
+        }
+
+        let Some(definition) = self.macros.get(name) else {
+            return Err(self.error(at, &format!("no macro named `{name}`")));
+        };
+        if definition.params.len() != args.len() {
+            return Err(self.error(
+                at,
+                &format!(
+                    "macro `{name}` takes {} args but was given {}",
+                    definition.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+
+        let params: HashMap<&str, &str> = definition
+            .params
+            .iter()
+            .map(String::as_str)
+            .zip(args)
+            .collect();
+        let body: Vec<_> = definition
+            .body
+            .iter()
+            .map(|(written, line)| (*written, substitute(line, &params)))
+            .collect();
+
+        let mut in_comment = false;
+        for (written, line) in body {
+            match strip_comments(&line, &mut in_comment).strip_prefix(MACRO_CALL) {
+                Some(_) if in_comment => return Err(self.error(written, UNCLOSED_COMMENT)),
+                Some(inner) => self.call(inner, written, depth + 1)?,
+                None => self.push(
+                    &line,
+                    Origin {
+                        written,
+                        expanded_at: Some(at),
+                    },
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, line: &str, origin: Origin) {
+        self.expanded.text.push_str(line);
+        self.expanded.text.push('\n');
+        self.expanded.lines.push(origin);
+    }
+
+    fn error(&self, at: Location, message: &str) -> BBScriptError {
+        BBScriptError::InvalidMacro(self.expanded.describe(at), message.to_string())
+    }
+}
+
+impl Expanded {
+    fn describe(&self, at: Location) -> String {
+        match &self.sources[at.source].name {
+            Some(name) => format!("{name}:{}", at.line),
+            None => format!("line {}", at.line),
+        }
+    }
+
+    /// Moves a syntax error in the expanded text to the file and line it was written at
+    pub(crate) fn locate(&self, error: pest_consume::Error<Rule>) -> pest_consume::Error<Rule> {
+        let (LineColLocation::Pos((line, col)) | LineColLocation::Span((line, col), _)) =
+            error.line_col;
+        // errors at the end of the script are on the line after the last one
+        let Some(origin) = self.lines.get(line - 1).or(self.lines.last()) else {
+            return error;
+        };
+
+        let source = &self.sources[origin.written.source];
+        let start: usize = source
+            .text
+            .split_inclusive('\n')
+            .take(origin.written.line - 1)
+            .map(str::len)
+            .sum();
+        let written = source.text[start..].lines().next().unwrap_or_default();
+        let offset = start
+            + written
+                .char_indices()
+                .nth(col - 1)
+                .map_or(written.len(), |(i, _)| i);
+        let Some(position) = pest::Position::new(&source.text, offset) else {
+            return error;
+        };
+
+        let variant = match origin.expanded_at {
+            Some(at) => ErrorVariant::CustomError {
+                message: format!(
+                    "{} (in a macro expanded at {})",
+                    error.variant.message(),
+                    self.describe(at)
+                ),
+            },
+            None => error.variant,
+        };
+
+        let located = pest::error::Error::new_from_pos(variant, position);
+        match &source.name {
+            Some(name) => located.with_path(name),
+            None => located,
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The line without indentation or comments, leaving comment markers inside quotes alone.
+/// `in_comment` carries a `/* */` comment over to the next line
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut code = String::with_capacity(line.len());
+    let mut quote = None;
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        let rest = &line[i..];
+        if *in_comment {
+            if rest.starts_with("*/") {
+                *in_comment = false;
+                chars.next();
+            }
+            continue;
+        }
+
+        match quote {
+            Some('\'') if c == '\\' => {
+                code.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    code.push(escaped);
+                }
+                continue;
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if rest.starts_with("//") => break,
+            None if rest.starts_with("/*") => {
+                *in_comment = true;
+                chars.next();
+                code.push(' ');
+                continue;
+            }
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None => {}
+        }
+        code.push(c);
+    }
+
+    code.trim().to_string()
+}
+
+/// Whether the line is replaced by the expansion instead of kept
+fn is_expanded(line: &str) -> bool {
+    line.starts_with(MACRO_CALL)
+        || [INCLUDE_DIRECTIVE, MACRO_DIRECTIVE, END_MACRO_DIRECTIVE]
+            .into_iter()
+            .any(|name| directive_args(line, name).is_some())
+}
+
+/// The args after `name` if `line` starts with it
+fn directive_args<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(name)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
+
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits `name(arg, ...)` or `name`, ignoring commas inside parentheses and quotes
+fn split_call(call: &str) -> Option<(&str, Vec<&str>)> {
+    let Some((name, args)) = call.split_once('(') else {
+        return is_name(call.trim()).then(|| (call.trim(), Vec::new()));
+    };
+    let name = name.trim();
+    let args = args.trim_end().strip_suffix(')')?;
+    if !is_name(name) {
+        return None;
+    }
+    if args.trim().is_empty() {
+        return Some((name, Vec::new()));
+    }
+
+    let mut split = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                split.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(args[start..].trim());
+
+    Some((name, split))
+}
+
+/// Replaces each `$param` in `line` with its arg
+fn substitute(line: &str, params: &HashMap<&str, &str>) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(dollar) = rest.find('$') {
+        result.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+
+        match params.get(&after[..len]) {
+            Some(arg) => result.push_str(arg),
+            None => result.push_str(&rest[dollar..dollar + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::BBScriptError;
+    use crate::rebuilder::{rebuild_bbscript_at, rebuild_readable};
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    const EXPANDED: &str = r"
+beginState: s32'CmnActLand'
+  sprite: s32'nmc000_00', 3
+  addPositionX: 1000, 0
+  exitState:
+endState:
+";
+
+    #[test]
+    fn include_and_macros() {
+        let config = SupportedGame::Ggst.into_config();
+        let dir = std::env::temp_dir().join(format!("bbscript_include_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("common.txt"),
+            "#macro land(frames, x)\n  sprite: s32'nmc000_00', $frames\n  @move($x)\n  exitState:\n#endmacro\n\
+             #macro move(x)\n  addPositionX: $x, 0\n#endmacro\n",
+        )
+        .unwrap();
+
+        let script =
+            "#include \"common.txt\"\nbeginState: s32'CmnActLand'\n  @land(3, 1000)\nendState:\n";
+        let expected = rebuild_readable::<LittleEndian>(&config, EXPANDED).unwrap();
+        assert_eq!(
+            rebuild_bbscript_at::<LittleEndian>(
+                SupportedGame::Ggst.into_config(),
+                script.into(),
+                &dir.join("main.txt")
+            )
+            .unwrap(),
+            expected
+        );
+
+        // syntax errors point to the file the line was written in
+        std::fs::write(
+            dir.join("common.txt"),
+            "#macro land(frames)\n  sprite: s32'nmc000_00', $frames)\n#endmacro\n",
+        )
+        .unwrap();
+        let script =
+            "#include \"common.txt\"\nbeginState: s32'CmnActLand'\n  @land(3)\nendState:\n";
+        let error = rebuild_bbscript_at::<LittleEndian>(
+            SupportedGame::Ggst.into_config(),
+            script.into(),
+            &dir.join("main.txt"),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("common.txt:2:"), "{error}");
+        assert!(error.contains("main.txt:3"), "{error}");
+
+        let error = rebuild_bbscript_at::<LittleEndian>(
+            SupportedGame::Ggst.into_config(),
+            "beginState: s32'A'\n  @land(3)\nendState:\n".into(),
+            &dir.join("main.txt"),
+        )
+        .unwrap_err();
+        assert!(matches!(error, BBScriptError::InvalidMacro(at, _) if at.ends_with("main.txt:2")));
+
+        let error =
+            rebuild_readable::<LittleEndian>(&config, "#macro loop\n  @loop\n#endmacro\n@loop\n")
+                .unwrap_err();
+        assert!(matches!(error, BBScriptError::InvalidMacro(at, _) if at == "line 2"));
+
+        // without a path there's nothing to resolve includes against
+        let error =
+            rebuild_readable::<LittleEndian>(&config, "#include \"common.txt\"\n").unwrap_err();
+        assert!(matches!(error, BBScriptError::InvalidMacro(at, _) if at == "line 1"));
+
+        // comments are skipped, but not comment markers inside strings
+        let script = "/* a macro\n#include \"missing.txt\"\n*/\n#macro sprite(name)\n  sprite: $name, 3\n#endmacro\n\
+                      beginState: s32'CmnActLand'\n  @sprite(s32'nmc//00') // a comment\nendState:\n";
+        assert_eq!(
+            rebuild_readable::<LittleEndian>(&config, script).unwrap(),
+            rebuild_readable::<LittleEndian>(
+                &config,
+                "beginState: s32'CmnActLand'\n  sprite: s32'nmc//00', 3\nendState:\n"
+            )
+            .unwrap()
+        );
+        let error =
+            rebuild_readable::<LittleEndian>(&config, "#macro a /* comment\n*/\n#endmacro\n")
+                .unwrap_err();
+        assert!(matches!(error, BBScriptError::InvalidMacro(at, _) if at == "line 1"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
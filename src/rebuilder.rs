@@ -1,45 +1,210 @@
-use std::{io::Write, ops::AddAssign};
+use std::io::Write;
+use std::path::Path;
 
 use crate::{
+    aliases::{ScriptAliases, VARIABLE_DIRECTIVE},
+    container::{Container, ContainerKind, LengthField},
     error::BBScriptError,
-    game_config::{ArgType, GenericInstruction, ScriptConfig, SizedString, UnsizedInstruction},
-    HashMap,
+    game_config::{
+        ArgType, GenericInstruction, JumpTableOptions, JumpTableOrder, ScriptConfig, SizedString,
+        TaggedValue, UnsizedInstruction,
+    },
+    header::{
+        BlockStyle, ScriptHeader, BLOCKS_DIRECTIVE, CONFIG_HASH_DIRECTIVE, CONTAINER_DIRECTIVE,
+        ENDIANNESS_DIRECTIVE, GAME_DIRECTIVE, JUMP_TABLE_ORDER_DIRECTIVE,
+    },
+    parser::{
+        ArgValue, InstructionIdentifier, InstructionValue, JumpEntry, JUMP_TABLE_NAME,
+        RAW_DATA_NAME,
+    },
+    preprocess::{expand, Expanded, INCLUDE_DIRECTIVE},
+    Endianness, SupportedGame,
 };
 
 use byteorder::{ByteOrder, WriteBytesExt};
 use pest_consume::{match_nodes, Parser};
 
+/// Rebuilds a readable script. It isn't read from a file, so `#include`s are an error, see [`rebuild_bbscript_at`]
 pub fn rebuild_bbscript<B: ByteOrder>(
     db: ScriptConfig,
     script: String,
 ) -> Result<Vec<u8>, BBScriptError> {
-    let root = BBSParser::parse(Rule::program, &script)
+    rebuild_readable::<B>(&db, &script)
+}
+
+/// Same as [`rebuild_bbscript`] for a script read from `path`, which `#include`s are resolved relative to
+/// and errors are reported in. Includes and macros are expanded before the script is assembled:
+///
+/// ```text
+/// #include "common.txt"
+///
+/// #macro land(frames)
+///   sprite: s32'nmc000_00', $frames
+///   exitState:
+/// #endmacro
+///
+/// beginState: s32'CmnActLand'
+///   @land(3)
+/// endState:
+/// ```
+pub fn rebuild_bbscript_at<B: ByteOrder>(
+    db: ScriptConfig,
+    script: String,
+    path: &Path,
+) -> Result<Vec<u8>, BBScriptError> {
+    rebuild_expanded::<B>(&db, expand(&script, Some(path))?)
+}
+
+/// Same as [`rebuild_bbscript`], without taking ownership of the config
+pub(crate) fn rebuild_readable<B: ByteOrder>(
+    db: &ScriptConfig,
+    script: &str,
+) -> Result<Vec<u8>, BBScriptError> {
+    rebuild_expanded::<B>(db, expand(script, None)?)
+}
+
+fn rebuild_expanded<B: ByteOrder>(
+    db: &ScriptConfig,
+    script: Expanded,
+) -> Result<Vec<u8>, BBScriptError> {
+    let (directives, statements) = parse_program(&script)?;
+    let header = ScriptHeader::from_directives(directives)?;
+
+    let mut aliases = db.aliases.clone();
+    aliases.declare_all(&statements)?;
+
+    let mut program = Vec::new();
+    flatten_blocks(db, &aliases, statements, &mut program)?;
+
+    if header
+        .config_hash
+        .is_some_and(|hash| hash != db.content_hash())
+    {
+        log::warn!("script was parsed with a different config than the one used to rebuild it");
+    }
+    if header
+        .endianness
+        .is_some_and(|e| e != Endianness::of::<B>())
+    {
+        log::warn!("script was parsed with a different byte order than the one used to rebuild it");
+    }
+
+    // the order the script was parsed with is what it has to be rebuilt with to get the same jump table
+    let jump_table = JumpTableOptions {
+        order: header.jump_table_order.unwrap_or(db.jump_table.order),
+        ..db.jump_table
+    };
+
+    let file = assemble_script::<B>(program, db, jump_table)?;
+
+    Ok(file)
+}
+
+fn parse_program(script: &Expanded) -> Result<(Vec<BBSFunction>, Vec<Statement>), BBScriptError> {
+    let root = BBSParser::parse(Rule::program, &script.text)
         .and_then(|p| p.single())
-        .map_err(Box::new)?;
+        .map_err(|e| Box::new(script.locate(e)))?;
 
     log::trace!("Parsed program AST:\n{:#?}", &root);
-    let program = BBSParser::program(root).map_err(Box::new)?;
+    Ok(BBSParser::program(root).map_err(|e| Box::new(script.locate(e)))?)
+}
 
-    let file = assemble_script::<B>(program, &db)?;
+/// Writes out the instructions of a script written with blocks, adding the instruction that ends each block
+/// and replacing the names declared in the script with their values
+fn flatten_blocks(
+    db: &ScriptConfig,
+    aliases: &ScriptAliases,
+    statements: Vec<Statement>,
+    program: &mut Vec<BBSFunction>,
+) -> Result<(), BBScriptError> {
+    for statement in statements {
+        match statement {
+            Statement::Variable(..) | Statement::Constant(..) => {}
+            Statement::Function(mut function) => {
+                aliases.resolve(&mut function)?;
+                program.push(function);
+            }
+            Statement::Block(mut begin, body) => {
+                aliases.resolve(&mut begin)?;
+                let begin = begin.lower_expression(db)?;
+                let end = db
+                    .block_ends
+                    .get(&begin.name)
+                    .ok_or_else(|| BBScriptError::NoBlockEnd(begin.name.clone()))?
+                    .clone();
 
-    Ok(file)
+                program.push(begin);
+                flatten_blocks(db, aliases, body, program)?;
+                program.push(BBSFunction {
+                    name: end,
+                    args: Vec::new(),
+                    expression: None,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rebuilds a script from the output of [`ScriptConfig::parse`], such as the JSON written by `parse-json`
+pub fn rebuild_instructions<B: ByteOrder>(
+    db: &ScriptConfig,
+    program: Vec<InstructionValue>,
+) -> Result<Vec<u8>, BBScriptError> {
+    let program = program.into_iter().map(BBSFunction::from).collect();
+
+    assemble_script::<B>(program, db, db.jump_table)
 }
 
 fn assemble_script<B: ByteOrder>(
     program: Vec<BBSFunction>,
     db: &ScriptConfig,
+    jump_table_options: JumpTableOptions,
 ) -> Result<Vec<u8>, BBScriptError> {
     // current position of the reader
     let mut offset: u32 = 0x0;
     let mut script_buffer: Vec<u8> = Vec::new();
 
-    // TODO: figure out behavior around eliminating duplicate state jump entries
-    let mut previous_jump_entries = std::collections::HashSet::new();
-
-    let mut jump_entry_counts = HashMap::<u32, u32>::new();
-    let mut jump_table_buffers: HashMap<u32, Vec<u8>> = HashMap::new();
+    // every instruction that can have a jump table entry, in script order
+    let mut states: Vec<JumpEntry> = Vec::new();
+    let mut recorded_jump_table: Option<Vec<RecordedEntry>> = None;
 
     for instruction in program {
+        let instruction = instruction.lower_expression(db)?;
+
+        if instruction.name == RAW_DATA_NAME {
+            let (mut data, entries) = instruction.into_raw_data()?;
+
+            for (table_id, name, relative_offset) in entries {
+                if !db.is_jump_entry_id(table_id) {
+                    return Err(BBScriptError::InvalidRawData(format!(
+                        "{table_id} is not a jump table ID"
+                    )));
+                }
+
+                states.push(JumpEntry {
+                    table_id,
+                    name,
+                    offset: offset + relative_offset,
+                });
+            }
+
+            log::debug!("writing {} bytes of raw data", data.len());
+            script_buffer.append(&mut data);
+            offset = script_buffer.len() as u32;
+
+            continue;
+        }
+
+        if instruction.name == JUMP_TABLE_NAME {
+            recorded_jump_table
+                .get_or_insert_with(Vec::new)
+                .append(&mut instruction.into_jump_table()?);
+
+            continue;
+        }
+
         log::debug!("finding info for {}", instruction.name);
         let instruction_info = if let Some(i) = db.get_by_name(&instruction.name) {
             i
@@ -83,27 +248,13 @@ fn assemble_script<B: ByteOrder>(
                 .unwrap();
         }
 
-        // build state jump table
         if db.is_jump_entry_id(instruction_info.id()) {
-            if let Some(ParserValue::String32(name)) = instruction.args.get(0) {
-                // this check deduplicates jump table entries
-                // TODO: make game-specific config option, seems to be needed
-                if previous_jump_entries.insert(name.0.clone()) {
-                    jump_table_buffers
-                        .entry(instruction_info.id())
-                        .or_insert(Vec::new())
-                        .write_all(&name.to_vec())
-                        .unwrap();
-                    jump_table_buffers
-                        .entry(instruction_info.id())
-                        .or_insert(Vec::new())
-                        .write_u32::<B>(offset)
-                        .unwrap();
-                    jump_entry_counts
-                        .entry(instruction_info.id())
-                        .or_insert(0)
-                        .add_assign(1);
-                }
+            if let Some(ParserValue::String32(name)) = instruction.args.first() {
+                states.push(JumpEntry {
+                    table_id: instruction_info.id(),
+                    name: name.clone(),
+                    offset,
+                });
             }
         }
 
@@ -156,6 +307,9 @@ fn assemble_script<B: ByteOrder>(
                     script_buffer.write_i32::<B>(db.literal_tag).unwrap();
                     script_buffer.write_i32::<B>(val).unwrap();
                 }
+                ParserValue::NamedVal(name) | ParserValue::Alias(name) => {
+                    return Err(BBScriptError::NoConstantName(name.to_string()));
+                }
                 &ParserValue::BadTag(tag, val) => {
                     log::trace!(
                         "Got bad tag {tag} with value {val} at offset {}",
@@ -168,31 +322,95 @@ fn assemble_script<B: ByteOrder>(
         }
         offset = script_buffer.len() as u32;
     }
+    let generated = db.generate_jump_table_with(states.clone(), jump_table_options);
+    let jump_table = match recorded_jump_table {
+        Some(recorded) => apply_recorded_jump_table(db, recorded, &states, generated),
+        None => generated,
+    };
+
     let mut result = Vec::new();
 
     // writes jump table counts in order specified by the config
     // DNF is the only game that uses that at the moment
     for id in &db.jump_table_ids {
-        let count = jump_entry_counts.get(id).unwrap_or(&0);
-        result.write_u32::<B>(*count).unwrap();
+        let count = jump_table.iter().filter(|e| e.table_id == *id).count();
+        result.write_u32::<B>(count as u32).unwrap();
     }
 
-    for id in &db.jump_table_ids {
-        if let Some(buffer) = jump_table_buffers.get_mut(id) {
-            result.append(buffer);
-        }
+    for entry in &jump_table {
+        result.write_all(&entry.name.to_vec()).unwrap();
+        result.write_u32::<B>(entry.offset).unwrap();
     }
     result.append(&mut script_buffer);
 
-    let result = result;
-
     Ok(result)
 }
 
+/// Builds the jump table in the order recorded by a [`JUMP_TABLE_NAME`] directive.
+/// Entries for states that no longer exist are dropped, and states the directive doesn't mention
+/// are added after the recorded entries of their table, the same way they would be generated
+fn apply_recorded_jump_table(
+    db: &ScriptConfig,
+    recorded: Vec<RecordedEntry>,
+    states: &[JumpEntry],
+    generated: Vec<JumpEntry>,
+) -> Vec<JumpEntry> {
+    let mut table = Vec::with_capacity(recorded.len());
+    let mut mentioned = std::collections::HashSet::new();
+
+    for (table_id, name, index) in recorded {
+        mentioned.insert((table_id, name.0.clone()));
+
+        let state = states
+            .iter()
+            .filter(|s| s.table_id == table_id && s.name.0 == name.0)
+            .nth(index);
+
+        match state {
+            Some(state) => table.push(state.clone()),
+            None => log::warn!("dropping jump table entry `{name}`, there is no matching state"),
+        }
+    }
+
+    table.extend(
+        generated
+            .into_iter()
+            .filter(|e| !mentioned.contains(&(e.table_id, e.name.0.clone()))),
+    );
+
+    // entries are stored grouped by table
+    table.sort_by_key(|e| db.jump_table_ids.iter().position(|id| *id == e.table_id));
+
+    table
+}
+
+#[derive(Debug)]
+enum Statement {
+    Function(BBSFunction),
+    /// An instruction opening a block written with braces, and the statements inside of it
+    Block(BBSFunction, Vec<Statement>),
+    /// `#var name = id`
+    Variable(String, i32),
+    /// `#const name = value`
+    Constant(String, i32),
+}
+
 #[derive(Debug)]
 struct BBSFunction {
     name: String,
     args: Vec<ParserValue>,
+    /// Set for instructions written as expressions, which have no name until they are lowered
+    expression: Option<Expression>,
+}
+
+/// An instruction written as an infix expression, see [`ExpressionSyntax`](crate::game_config::ExpressionSyntax)
+#[derive(Debug)]
+struct Expression {
+    /// Keyword of a condition, `None` for an assignment
+    keyword: Option<String>,
+    operator: String,
+    lhs: ParserValue,
+    rhs: ParserValue,
 }
 
 impl BBSFunction {
@@ -210,8 +428,10 @@ impl BBSFunction {
                 ParserValue::NamedMem(_) => 8,
                 ParserValue::Val(_) => 8,
                 ParserValue::BadTag(_, _) => 8,
+                ParserValue::NamedVal(_) => 8,
                 ParserValue::Named(_) => 4,
                 ParserValue::Number(_) => 4,
+                ParserValue::Alias(_) => 4,
             })
             .sum();
 
@@ -219,6 +439,352 @@ impl BBSFunction {
     }
 }
 
+impl BBSFunction {
+    /// Splits a raw data pseudo-instruction into its data and the jump table entries inside of it
+    fn into_raw_data(self) -> Result<RawData, BBScriptError> {
+        let mut args = self.args.into_iter();
+
+        let data = match args.next() {
+            Some(ParserValue::Raw(data)) => data,
+            _ => {
+                return Err(BBScriptError::InvalidRawData(
+                    "first argument must be hex data".into(),
+                ))
+            }
+        };
+
+        let mut entries = Vec::new();
+        loop {
+            match (args.next(), args.next(), args.next()) {
+                (None, None, None) => break,
+                (
+                    Some(ParserValue::Number(table_id)),
+                    Some(ParserValue::String32(name)),
+                    Some(ParserValue::Number(relative_offset)),
+                ) if (0..data.len() as i32).contains(&relative_offset) => {
+                    entries.push((table_id as u32, name, relative_offset as u32))
+                }
+                _ => return Err(BBScriptError::InvalidRawData(
+                    "jump table entries must be a table ID, s32 name, and offset within the data"
+                        .into(),
+                )),
+            }
+        }
+
+        Ok((data, entries))
+    }
+
+    /// Turns an instruction written as an expression into the instruction it stands for,
+    /// with the operation and operands put in front of its other args
+    fn lower_expression(self, db: &ScriptConfig) -> Result<Self, BBScriptError> {
+        let Some(expression) = self.expression else {
+            return Ok(self);
+        };
+        let syntax = &db.expressions;
+
+        let (name, operators) = match &expression.keyword {
+            Some(keyword) => (
+                syntax
+                    .conditions
+                    .iter()
+                    .find(|(_, k)| *k == keyword)
+                    .map(|(name, _)| name.clone())
+                    .ok_or_else(|| {
+                        BBScriptError::InvalidExpression(format!(
+                            "no instruction is written as `{keyword}`"
+                        ))
+                    })?,
+                &syntax.condition_operators,
+            ),
+            None => (
+                syntax.assignment.clone().ok_or_else(|| {
+                    BBScriptError::InvalidExpression("the config has no assignment syntax".into())
+                })?,
+                &syntax.assignment_operators,
+            ),
+        };
+
+        let operation = operators
+            .iter()
+            .find(|(_, o)| **o == expression.operator)
+            .map(|(operation, _)| operation.clone())
+            .ok_or_else(|| {
+                BBScriptError::InvalidExpression(format!(
+                    "`{}` is not an operator of `{name}`",
+                    expression.operator
+                ))
+            })?;
+
+        let info = db
+            .get_by_name(&name)
+            .ok_or_else(|| BBScriptError::UnknownInstructionName(name.clone()))?;
+        let index = syntax
+            .operation_index(info.args())
+            .filter(|index| *index <= self.args.len())
+            .ok_or_else(|| {
+                BBScriptError::InvalidExpression(format!(
+                    "`{name}` doesn't take an operation followed by two values there"
+                ))
+            })?;
+
+        let mut args = self.args;
+        args.splice(
+            index..index,
+            [
+                ParserValue::Named(operation),
+                expression.lhs,
+                expression.rhs,
+            ],
+        );
+
+        Ok(Self {
+            name,
+            args,
+            expression: None,
+        })
+    }
+
+    /// Splits a jump table directive into its entries
+    fn into_jump_table(self) -> Result<Vec<RecordedEntry>, BBScriptError> {
+        let mut args = self.args.into_iter();
+
+        let mut entries = Vec::new();
+        loop {
+            match (args.next(), args.next(), args.next()) {
+                (None, None, None) => break,
+                (
+                    Some(ParserValue::Number(table_id)),
+                    Some(ParserValue::String32(name)),
+                    Some(ParserValue::Number(index)),
+                ) if index >= 0 => entries.push((table_id as u32, name, index as usize)),
+                _ => {
+                    return Err(BBScriptError::InvalidJumpTable(
+                        "entries must be a table ID, s32 name, and state index".into(),
+                    ))
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl ScriptAliases {
+    /// Reads the `#var` and `#const` declarations of a readable script,
+    /// such as an alias file that only declares names for other scripts to include
+    pub fn read(script: &str) -> Result<Self, BBScriptError> {
+        Self::read_expanded(expand(script, None)?)
+    }
+
+    /// Same as [`ScriptAliases::read`] for a script read from `path`, which `#include`s are resolved relative to
+    pub fn read_at(script: &str, path: &Path) -> Result<Self, BBScriptError> {
+        Self::read_expanded(expand(script, Some(path))?)
+    }
+
+    fn read_expanded(script: Expanded) -> Result<Self, BBScriptError> {
+        let (_, statements) = parse_program(&script)?;
+
+        let mut aliases = Self::default();
+        aliases.declare_all(&statements)?;
+
+        Ok(aliases)
+    }
+
+    fn declare_all(&mut self, statements: &[Statement]) -> Result<(), BBScriptError> {
+        for statement in statements {
+            match statement {
+                Statement::Variable(name, id) => self.declare_variable(name.clone(), *id)?,
+                Statement::Constant(name, value) => self.declare_constant(name.clone(), *value)?,
+                Statement::Block(_, body) => self.declare_all(body)?,
+                Statement::Function(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces declared names in the args and operands of an instruction with their values.
+    /// Variables that aren't declared are left to be looked up in the config
+    fn resolve(&self, function: &mut BBSFunction) -> Result<(), BBScriptError> {
+        for arg in &mut function.args {
+            self.resolve_value(arg, false)?;
+        }
+        if let Some(expression) = &mut function.expression {
+            self.resolve_value(&mut expression.lhs, true)?;
+            self.resolve_value(&mut expression.rhs, true)?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_value(&self, value: &mut ParserValue, operand: bool) -> Result<(), BBScriptError> {
+        let constant = |name: &String| {
+            self.constants
+                .get(name)
+                .copied()
+                .ok_or_else(|| BBScriptError::NoConstantName(name.clone()))
+        };
+
+        *value = match value {
+            ParserValue::NamedMem(name) => match self.variables.get_by_right(name) {
+                Some(id) => ParserValue::Mem(*id),
+                None => return Ok(()),
+            },
+            ParserValue::NamedVal(name) => ParserValue::Val(constant(name)?),
+            // bare names in expressions are variables unless they are constants
+            ParserValue::Alias(name) if operand && !self.constants.contains_key(name) => {
+                match self.variables.get_by_right(name) {
+                    Some(id) => ParserValue::Mem(*id),
+                    None => ParserValue::NamedMem(name.clone()),
+                }
+            }
+            ParserValue::Alias(name) if operand => ParserValue::Val(constant(name)?),
+            ParserValue::Alias(name) => ParserValue::Number(constant(name)?),
+            _ => return Ok(()),
+        };
+
+        Ok(())
+    }
+}
+
+impl ScriptHeader {
+    /// Reads the header directives at the top of a readable script, without assembling the rest of it
+    pub fn read(script: &str) -> Result<Self, BBScriptError> {
+        let root = BBSParser::parse(Rule::header, script)
+            .and_then(|p| p.single())
+            .map_err(Box::new)?;
+        let directives = BBSParser::header(root).map_err(Box::new)?;
+
+        Self::from_directives(directives)
+    }
+
+    fn from_directives(directives: Vec<BBSFunction>) -> Result<Self, BBScriptError> {
+        let mut header = Self::default();
+
+        for directive in directives {
+            let invalid = |expected: &str| {
+                BBScriptError::InvalidHeader(format!("`{}` takes {expected}", directive.name))
+            };
+
+            match (directive.name.as_str(), directive.args.as_slice()) {
+                (GAME_DIRECTIVE, [ParserValue::Named(name)]) => {
+                    header.game = Some(
+                        variant_named(&SupportedGame::ALL, name)
+                            .ok_or_else(|| invalid("a supported game"))?,
+                    );
+                }
+                (CONFIG_HASH_DIRECTIVE, [ParserValue::Raw(hash)]) => {
+                    let hash = hash
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| invalid("an 8 byte hash"))?;
+                    header.config_hash = Some(u64::from_be_bytes(hash));
+                }
+                (ENDIANNESS_DIRECTIVE, [ParserValue::Named(name)]) => {
+                    header.endianness = Some(
+                        variant_named(&[Endianness::Little, Endianness::Big], name)
+                            .ok_or_else(|| invalid("(Little) or (Big)"))?,
+                    );
+                }
+                (JUMP_TABLE_ORDER_DIRECTIVE, [ParserValue::Named(name)]) => {
+                    header.jump_table_order = Some(
+                        variant_named(&[JumpTableOrder::Script, JumpTableOrder::Name], name)
+                            .ok_or_else(|| invalid("(Script) or (Name)"))?,
+                    );
+                }
+                (INCLUDE_DIRECTIVE, [ParserValue::Named(path)]) => {
+                    header.aliases = Some(path.clone());
+                }
+                (BLOCKS_DIRECTIVE, [ParserValue::Named(name)]) => {
+                    header.block_style = Some(
+                        variant_named(&[BlockStyle::Indented, BlockStyle::Braces], name)
+                            .ok_or_else(|| invalid("(Indented) or (Braces)"))?,
+                    );
+                }
+                (
+                    CONTAINER_DIRECTIVE,
+                    [ParserValue::Named(kind), ParserValue::Named(endianness), ParserValue::Raw(prefix), ParserValue::Raw(suffix), length_fields @ ..],
+                ) => {
+                    let expected = "a container kind, byte order, prefix, suffix, \
+                                    and the offset and extra size of each length field";
+
+                    let kind =
+                        variant_named(&CONTAINER_KINDS, kind).ok_or_else(|| invalid(expected))?;
+                    let endianness =
+                        variant_named(&[Endianness::Little, Endianness::Big], endianness)
+                            .ok_or_else(|| invalid(expected))?;
+
+                    let mut fields = Vec::new();
+                    for field in length_fields.chunks(2) {
+                        match *field {
+                            [ParserValue::Number(offset), ParserValue::Number(extra)]
+                                if offset >= 0 && offset as usize + 4 <= prefix.len() =>
+                            {
+                                fields.push(LengthField {
+                                    offset: offset as usize,
+                                    extra: extra as u32,
+                                })
+                            }
+                            _ => return Err(invalid(expected)),
+                        }
+                    }
+
+                    header.container = Some(Container {
+                        kind,
+                        endianness,
+                        prefix: prefix.clone(),
+                        suffix: suffix.clone(),
+                        length_fields: fields,
+                    });
+                }
+                (_, _) => return Err(invalid("different arguments")),
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+const CONTAINER_KINDS: [ContainerKind; 3] = [
+    ContainerKind::Ue3Export,
+    ContainerKind::Ue4Export,
+    ContainerKind::Sliced,
+];
+
+/// Finds the variant of a fieldless enum written with its `Debug` name
+fn variant_named<T: std::fmt::Debug + Copy>(variants: &[T], name: &str) -> Option<T> {
+    variants.iter().copied().find(|v| format!("{v:?}") == name)
+}
+
+/// A jump table entry from a [`JUMP_TABLE_NAME`] directive, as its table ID, name, and index among the states with that name
+type RecordedEntry = (u32, SizedString<32>, usize);
+
+/// Data of a raw data pseudo-instruction, and the `(table ID, name, offset)` of any jump table entries inside of it
+type RawData = (Vec<u8>, Vec<(u32, SizedString<32>, u32)>);
+
+impl From<InstructionValue> for BBSFunction {
+    fn from(instruction: InstructionValue) -> Self {
+        let name = match instruction.identifier {
+            InstructionIdentifier::Name(name) => name,
+            InstructionIdentifier::Id(id) => format!("Unknown{id}"),
+            InstructionIdentifier::RawData => RAW_DATA_NAME.to_string(),
+            InstructionIdentifier::JumpTable => JUMP_TABLE_NAME.to_string(),
+        };
+
+        let args = instruction
+            .args
+            .into_iter()
+            .map(ParserValue::from)
+            .collect();
+
+        Self {
+            name,
+            args,
+            expression: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 enum ParserValue {
     String32(SizedString<32>),
@@ -229,7 +795,11 @@ enum ParserValue {
     NamedMem(String),
     Mem(i32),
     Val(i32),
+    /// A constant used in `Val(...)`
+    NamedVal(String),
     BadTag(i32, i32),
+    /// A bare name, which is a constant or, in expressions, also a variable
+    Alias(String),
 }
 
 impl ParserValue {
@@ -244,7 +814,27 @@ impl ParserValue {
             ParserValue::NamedMem(_) => AccessedValue,
             ParserValue::Mem(_) => AccessedValue,
             ParserValue::Val(_) => AccessedValue,
+            ParserValue::NamedVal(_) => AccessedValue,
             ParserValue::BadTag(_, _) => AccessedValue,
+            ParserValue::Alias(_) => Number,
+        }
+    }
+}
+
+impl From<ArgValue> for ParserValue {
+    fn from(arg: ArgValue) -> Self {
+        match arg {
+            ArgValue::Unknown(data) => ParserValue::Raw(data.into_vec()),
+            ArgValue::Number(num) => ParserValue::Number(num),
+            ArgValue::String16(string) => ParserValue::String16(string),
+            ArgValue::String32(string) => ParserValue::String32(string),
+            ArgValue::AccessedValue(TaggedValue::Literal(val)) => ParserValue::Val(val),
+            ArgValue::AccessedValue(TaggedValue::Variable(var_id)) => ParserValue::Mem(var_id),
+            ArgValue::AccessedValue(TaggedValue::Improper { tag, value }) => {
+                ParserValue::BadTag(tag, value)
+            }
+            // the raw value is already known, so there is no need to look up the variant name
+            ArgValue::Enum(_, val) => ParserValue::Number(val),
         }
     }
 }
@@ -262,24 +852,147 @@ impl BBSParser {
         Ok(())
     }
 
-    fn program(input: Node) -> PResult<Vec<BBSFunction>> {
+    fn program(input: Node) -> PResult<(Vec<BBSFunction>, Vec<Statement>)> {
+        Ok(match_nodes!(input.into_children();
+            [header(directives), statement(statements)..,EOI(_)] => (directives, statements.collect()),
+        ))
+    }
+
+    fn statement(input: Node) -> PResult<Statement> {
+        Ok(match_nodes!(input.into_children();
+            [declaration(declaration)] => declaration,
+            [block(block)] => block,
+            [function(function)] => Statement::Function(function),
+        ))
+    }
+
+    fn block(input: Node) -> PResult<Statement> {
+        Ok(match_nodes!(input.into_children();
+            [function(begin), statement(body)..] => Statement::Block(begin, body.collect()),
+        ))
+    }
+
+    fn declaration(input: Node) -> PResult<Statement> {
         Ok(match_nodes!(input.into_children();
-            [function(functions)..,EOI(_)] => functions.collect(),
+            [declaration_kind(kind), alias(name), num(value)] => match kind.as_str() {
+                VARIABLE_DIRECTIVE => Statement::Variable(name, value),
+                _ => Statement::Constant(name, value),
+            },
         ))
     }
 
+    fn declaration_kind(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
+    fn alias(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
+    fn header(input: Node) -> PResult<Vec<BBSFunction>> {
+        Ok(match_nodes!(input.into_children();
+            [directive(directives)..] => directives.collect(),
+        ))
+    }
+
+    fn directive(input: Node) -> PResult<BBSFunction> {
+        let input = input.into_children();
+        let none = Vec::new();
+
+        let directive = match_nodes!(input;
+            [directive_name(name), args(args)] => BBSFunction { name, args, expression: None },
+            [directive_name(name)] => BBSFunction { name, args: none, expression: None },
+            [include_path(path)] => BBSFunction {
+                name: INCLUDE_DIRECTIVE.into(),
+                args: vec![ParserValue::Named(path)],
+                expression: None,
+            },
+        );
+
+        Ok(directive)
+    }
+
+    fn directive_name(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
+    fn include_path(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
     fn function(input: Node) -> PResult<BBSFunction> {
         let input = input.into_children();
         let none = Vec::new();
 
         let func = match_nodes!(input;
-            [function_name(name), args(args)] => BBSFunction { name, args },
-            [function_name(name)] => BBSFunction { name, args: none }
+            [condition(func)] => func,
+            [assignment(func)] => func,
+            [function_name(name), args(args)] => BBSFunction { name, args, expression: None },
+            [function_name(name)] => BBSFunction { name, args: none, expression: None }
         );
 
         Ok(func)
     }
 
+    fn condition(input: Node) -> PResult<BBSFunction> {
+        let (keyword, lhs, operator, rhs, args) = match_nodes!(input.into_children();
+            [keyword(k), operand(l), operator(o), operand(r), args(a)] => (k, l, o, r, a),
+            [keyword(k), operand(l), operator(o), operand(r)] => (k, l, o, r, Vec::new()),
+        );
+
+        Ok(BBSFunction {
+            name: String::new(),
+            args,
+            expression: Some(Expression {
+                keyword: Some(keyword),
+                operator,
+                lhs,
+                rhs,
+            }),
+        })
+    }
+
+    fn assignment(input: Node) -> PResult<BBSFunction> {
+        let (lhs, operator, rhs, args) = match_nodes!(input.into_children();
+            [operand(l), operator(o), operand(r), args(a)] => (l, o, r, a),
+            [operand(l), operator(o), operand(r)] => (l, o, r, Vec::new()),
+        );
+
+        Ok(BBSFunction {
+            name: String::new(),
+            args,
+            expression: Some(Expression {
+                keyword: None,
+                operator,
+                lhs,
+                rhs,
+            }),
+        })
+    }
+
+    fn keyword(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
+    fn operand(input: Node) -> PResult<ParserValue> {
+        Ok(match_nodes!(input.into_children();
+            [named_var(name)] => ParserValue::NamedMem(name),
+            [operand_var(name)] => ParserValue::Alias(name),
+            [var_id(val)] => ParserValue::Mem(val),
+            [tagged_value(val)] => ParserValue::Val(val),
+            [val_name(name)] => ParserValue::NamedVal(name),
+            [unknown_tag(tag), tagged_value(val)] => ParserValue::BadTag(tag, val),
+        ))
+    }
+
+    fn operand_var(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
+    fn operator(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
     fn function_name(input: Node) -> PResult<String> {
         Ok(input.as_str().into())
     }
@@ -301,6 +1014,8 @@ impl BBSParser {
             [unknown_tag(tag), tagged_value(val)] => ParserValue::BadTag(tag, val),
             [raw_data(data)] => ParserValue::Raw(data),
             [num(val)] => ParserValue::Number(val),
+            [val_name(name)] => ParserValue::NamedVal(name),
+            [constant(name)] => ParserValue::Alias(name),
         ))
     }
 
@@ -330,6 +1045,14 @@ impl BBSParser {
         }
     }
 
+    fn val_name(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
+    fn constant(input: Node) -> PResult<String> {
+        Ok(input.as_str().into())
+    }
+
     fn named_value(input: Node) -> PResult<String> {
         Ok(input.as_str().into())
     }
@@ -359,3 +1082,160 @@ impl BBSParser {
 fn unescaped<T: AsRef<str>>(string: T) -> String {
     string.as_ref().replace(r"\'", r"'")
 }
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::bytes;
+    use crate::header::BlockStyle;
+    use crate::parser::ParseRecovery;
+    use crate::rebuilder::rebuild_readable;
+    use crate::{
+        rebuild_instructions, BBScriptError, InstructionValue, ScriptHeader, SupportedGame,
+    };
+    use byteorder::LittleEndian;
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+  upon: (IMMEDIATE)
+    modifyVar: (ADD), Mem(PlayerVal0), Val(5)
+  endUpon:
+  ifOperation: (IS_GREATER), Mem(Tmp), BadTag(7,100)
+    exitState:
+  endIf:
+endState:
+";
+
+    #[test]
+    fn json_round_trip() {
+        let config = SupportedGame::Ggst.into_config();
+        let original = bytes(SCRIPT);
+
+        let program = config.parse::<LittleEndian>(&original).unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        let program: Vec<InstructionValue> = serde_json::from_str(&json).unwrap();
+
+        let rebuilt = rebuild_instructions::<LittleEndian>(&config, program).unwrap();
+
+        assert_eq!(original, rebuilt);
+    }
+
+    #[test]
+    fn recorded_jump_table_round_trip() {
+        let config = SupportedGame::Ggst.into_config();
+        let mut keep_duplicates = SupportedGame::Ggst.into_config();
+        keep_duplicates.jump_table.deduplicate = false;
+
+        let script = "beginState: s32'CmnActStand'\nendState:\n\
+                      beginState: s32'CmnActCrouch'\nendState:\n\
+                      beginState: s32'CmnActStand'\nendState:\n";
+        let mut original = rebuild_readable::<LittleEndian>(&keep_duplicates, script).unwrap();
+
+        // swap the first two entries, so the table is neither deduplicated nor in script order
+        let (first, second) = original[4..4 + 2 * 0x24].split_at_mut(0x24);
+        first.swap_with_slice(second);
+
+        let readable = config
+            .parse_to_string::<LittleEndian>(&original, 1)
+            .unwrap();
+        assert!(readable.contains(
+            "\n#jumpTable: 0, s32'CmnActCrouch', 0, 0, s32'CmnActStand', 0, 0, s32'CmnActStand', 1\n"
+        ));
+        assert_eq!(
+            rebuild_readable::<LittleEndian>(&config, &readable).unwrap(),
+            original
+        );
+
+        let program = config.parse::<LittleEndian>(&original).unwrap();
+        assert_eq!(
+            rebuild_instructions::<LittleEndian>(&config, program).unwrap(),
+            original
+        );
+
+        // scripts matching the generated table don't record it
+        let generated = rebuild_readable::<LittleEndian>(&config, script).unwrap();
+        let readable = config
+            .parse_to_string::<LittleEndian>(&generated, 1)
+            .unwrap();
+        assert!(!readable.contains("#jumpTable:"));
+    }
+
+    #[test]
+    fn expression_round_trip() {
+        let config = SupportedGame::Ggst.into_config();
+        let normal = r"
+beginState: s32'CmnActStand'
+  ifOperation: (IS_GREATER), Mem(PosX), Val(100)
+    modifyVar: (ADD), Mem(PlayerVal0), Val(-5)
+    modifyVar: (MOD_EQUALS_0), Mem(PlayerVal0), Val(2)
+  endIf:
+  gotoIfOperation: s32'label', (IS_EQUAL), Mem(9999), BadTag(7,100)
+endState:
+";
+        let infix = r"
+beginState: s32'CmnActStand'
+  if (PosX > 100)
+    PlayerVal0 += -5
+    modifyVar: (MOD_EQUALS_0), Mem(PlayerVal0), Val(2)
+  endIf:
+  gotoIf (Mem(9999) == BadTag(7,100)): s32'label'
+endState:
+";
+
+        let bytes = rebuild_readable::<LittleEndian>(&config, normal).unwrap();
+        assert_eq!(
+            rebuild_readable::<LittleEndian>(&config, infix).unwrap(),
+            bytes
+        );
+
+        let readable = config.parse_to_string::<LittleEndian>(&bytes, 12).unwrap();
+        assert!(readable.contains("  if (PosX > 100)\n    PlayerVal0 += -5\n"));
+        // operations without an operator keep the normal syntax
+        assert!(readable.contains("modifyVar: (MOD_EQUALS_0), Mem(PlayerVal0), Val(2)\n"));
+        assert!(readable.contains("gotoIf (Mem(9999) == BadTag(7,100)): s32'label'\n"));
+
+        assert!(rebuild_readable::<LittleEndian>(&config, "PosX <=> 5\n").is_err());
+        assert!(rebuild_readable::<LittleEndian>(&config, "while (PosX > 5)\n").is_err());
+    }
+
+    #[test]
+    fn brace_blocks() {
+        let config = SupportedGame::Ggst.into_config();
+        let header = ScriptHeader {
+            block_style: Some(BlockStyle::Braces),
+            ..ScriptHeader::new::<LittleEndian>(&config)
+        };
+
+        // the last `ifOperation` is never closed, so it can't be written with braces
+        let script = r"
+beginState: s32'CmnActStand'
+  upon: (IMMEDIATE)
+    modifyVar: (ADD), Mem(PlayerVal0), Val(5)
+  endUpon:
+  ifOperation: (IS_GREATER), Mem(PosX), Val(1)
+endState:
+";
+        let bytes = rebuild_readable::<LittleEndian>(&config, script).unwrap();
+
+        let readable = config
+            .parse_to_string_with_header::<LittleEndian>(&bytes, 12, ParseRecovery::Strict, &header)
+            .unwrap();
+        assert!(readable.contains(
+            "beginState: s32'CmnActStand' {\n  upon: (IMMEDIATE) {\n    PlayerVal0 += 5\n  }\n  if (PosX > 1)\n}\n"
+        ));
+        assert_eq!(
+            rebuild_readable::<LittleEndian>(&config, &readable).unwrap(),
+            bytes
+        );
+
+        for unbalanced in [
+            "beginState: s32'A' {\n  upon: (IMMEDIATE) {\n}\n",
+            "beginState: s32'A' {\n}\n}\n",
+        ] {
+            assert!(matches!(
+                rebuild_readable::<LittleEndian>(&config, unbalanced),
+                Err(BBScriptError::PestConsumeError(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use byteorder::ByteOrder;
+use serde::Serialize;
+
+use crate::ast::{NodeId, NodeKind, Script};
+use crate::diff::named_roots;
+use crate::error::BBScriptError;
+use crate::game_config::ScriptConfig;
+use crate::parser::{LocatedInstruction, ReadableWriter};
+use crate::rebuilder::rebuild_readable;
+
+/// Indent limit of states written on their own
+pub(crate) const STATE_INDENT_LIMIT: usize = 12;
+
+/// Where a state or subroutine is in a script
+#[derive(Debug, Clone, Serialize)]
+pub struct StateInfo {
+    pub kind: NodeKind,
+    pub name: String,
+    /// Offset from the start of the script, including the jump table
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// A script split into the readable form of each top level node, so single states can be replaced before assembling it again
+pub(crate) struct ReadableRoots {
+    /// The `#jumpTable` directive of the original script, if it needs one
+    jump_table: String,
+    /// Readable form of every top level node, along with the name of named ones
+    pub(crate) roots: Vec<(Option<String>, String)>,
+}
+
+impl ReadableRoots {
+    pub(crate) fn new(config: &ScriptConfig, script: &Script) -> Result<Self, BBScriptError> {
+        let named = first_named_roots(script);
+        let mut roots = Vec::with_capacity(script.roots().len());
+        for id in script.roots() {
+            let name = named
+                .iter()
+                .find(|(_, n)| n == id)
+                .map(|(name, _)| name.clone());
+            roots.push((name, render(config, script, *id)?));
+        }
+
+        // states that are left keep their original jump table order
+        let mut jump_table = String::new();
+        if let Some(instruction) = script.jump_table() {
+            let located = LocatedInstruction {
+                offset: 0,
+                size: 0,
+                instruction: instruction.clone(),
+            };
+            ReadableWriter::new(STATE_INDENT_LIMIT).write(config, &mut jump_table, &located)?;
+        }
+
+        Ok(Self { jump_table, roots })
+    }
+
+    pub(crate) fn position(&self, name: &str) -> Option<usize> {
+        self.roots
+            .iter()
+            .position(|(n, _)| n.as_deref() == Some(name))
+    }
+
+    pub(crate) fn assemble<B: ByteOrder>(
+        &self,
+        config: &ScriptConfig,
+    ) -> Result<Vec<u8>, BBScriptError> {
+        let mut text = self.jump_table.clone();
+        for (_, root) in &self.roots {
+            text.push_str(root);
+        }
+
+        rebuild_readable::<B>(config, &text)
+    }
+}
+
+impl ScriptConfig {
+    /// Lists every named state and subroutine in a script
+    pub fn list_states<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Vec<StateInfo>, BBScriptError> {
+        let input = input.as_ref();
+        let (_, base) = self.read_jump_table::<B>(input)?;
+        let script = self.parse_tree::<B>(input)?;
+
+        Ok(named_roots(&script)
+            .into_iter()
+            .map(|((kind, name, _), id)| {
+                let span = script.span(id);
+                StateInfo {
+                    kind,
+                    name,
+                    offset: base + span.start,
+                    size: span.len(),
+                }
+            })
+            .collect())
+    }
+
+    /// Writes the chosen states and subroutines in the readable format, in the order they are given
+    pub fn extract_states<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        names: &[impl AsRef<str>],
+    ) -> Result<String, BBScriptError> {
+        let script = self.parse_tree::<B>(input)?;
+        let roots = first_named_roots(&script);
+
+        let mut out = String::new();
+        for name in names {
+            let name = name.as_ref();
+            let (_, id) = roots
+                .iter()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| BBScriptError::StateNotFound(name.into()))?;
+
+            out.push_str(&render(self, &script, *id)?);
+        }
+
+        Ok(out)
+    }
+
+    /// Puts the states and subroutines of a readable fragment into a script,
+    /// replacing the ones with the same name and appending the others
+    pub fn splice_states<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+        fragment: &str,
+    ) -> Result<Vec<u8>, BBScriptError> {
+        let input = input.as_ref();
+        let script = self.parse_tree::<B>(input)?;
+        let mut roots = ReadableRoots::new(self, &script)?;
+
+        // assembling the fragment on its own checks it and splits it into states
+        let fragment = self.parse_tree::<B>(rebuild_readable::<B>(self, fragment)?)?;
+        for (name, id) in first_named_roots(&fragment) {
+            let text = render(self, &fragment, id)?;
+
+            match roots.position(&name) {
+                Some(index) => roots.roots[index].1 = text,
+                None => roots.roots.push((Some(name), text)),
+            }
+        }
+
+        roots.assemble::<B>(self)
+    }
+}
+
+/// Named states and subroutines, leaving out any later ones with the same name as an earlier one
+pub(crate) fn first_named_roots(script: &Script) -> Vec<(String, NodeId)> {
+    named_roots(script)
+        .into_iter()
+        .filter(|((_, _, occurrence), _)| *occurrence == 0)
+        .map(|((_, name, _), id)| (name, id))
+        .collect()
+}
+
+/// Readable form of a single node
+pub(crate) fn render(
+    config: &ScriptConfig,
+    script: &Script,
+    id: NodeId,
+) -> Result<String, BBScriptError> {
+    let mut out = String::new();
+    let mut writer = ReadableWriter::new(STATE_INDENT_LIMIT);
+
+    for instruction in script.node_instructions(id) {
+        writer.write(config, &mut out, instruction)?;
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::NodeKind;
+    use crate::fixture::bytes;
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    const SCRIPT: &str = r"
+beginState: s32'CmnActStand'
+  sprite: s32'nmc000_00', 5
+endState:
+beginSubroutine: s32'cmnSub'
+  sprite: s32'nmc000_01', 2
+endSubroutine:
+beginState: s32'CmnActCrouch'
+  callSubroutine: s32'cmnSub'
+endState:
+";
+
+    #[test]
+    fn list_extract_splice() {
+        let config = SupportedGame::Ggst.into_config();
+        let script = bytes(SCRIPT);
+
+        let states = config.list_states::<LittleEndian>(&script).unwrap();
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[1].kind, NodeKind::Subroutine);
+        assert_eq!(
+            (states[0].offset, states[0].size),
+            (4 + 2 * 0x24, 0x24 + 0x28 + 4)
+        );
+        assert_eq!(states[2].offset, states[1].offset + states[1].size);
+
+        let extracted = config
+            .extract_states::<LittleEndian>(&script, &["CmnActCrouch"])
+            .unwrap();
+        assert!(extracted.starts_with("beginState: s32'CmnActCrouch'\n"));
+        assert!(config
+            .extract_states::<LittleEndian>(&script, &["Missing"])
+            .is_err());
+
+        let fragment = extracted
+            .replace("callSubroutine: s32'cmnSub'", "sprite: s32'nmc001_00', 3")
+            + "beginState: s32'CmnActJump'\nendState:\n";
+        let spliced = config
+            .splice_states::<LittleEndian>(&script, &fragment)
+            .unwrap();
+
+        let expected = SCRIPT.replace("callSubroutine: s32'cmnSub'", "sprite: s32'nmc001_00', 3")
+            + "beginState: s32'CmnActJump'\nendState:\n";
+        assert_eq!(spliced, bytes(&expected));
+    }
+}
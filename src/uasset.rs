@@ -0,0 +1,358 @@
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+
+use std::io::{Cursor, Seek, SeekFrom};
+
+use crate::container::{Container, ContainerKind, UE4_PACKAGE_TAG};
+use crate::error::BBScriptError;
+
+/// Package flag set on cooked packages, which leaves out editor only data from the summary
+const PKG_FILTER_EDITOR_ONLY: u32 = 0x80000000;
+/// First version with 64-bit serial sizes and every other field in the export map layout read here
+const VER_UE4_64BIT_EXPORTMAP_SERIALSIZES: i32 = 511;
+const VER_UE4_ADDED_PACKAGE_SUMMARY_LOCALIZATION_ID: i32 = 516;
+const VER_UE4_SERIALIZE_TEXT_IN_PACKAGES: i32 = 459;
+const VER_UE4_ADDED_PACKAGE_OWNER: i32 = 518;
+const VER_UE4_NON_OUTER_PACKAGE_IMPORT: i32 = 520;
+/// Size of an export map entry from [`VER_UE4_64BIT_EXPORTMAP_SERIALSIZES`] up to UE 4.27
+const EXPORT_ENTRY_SIZE: usize = 104;
+/// Offsets of `SerialSize` and `SerialOffset` within an export map entry
+const SERIAL_SIZE_OFFSET: usize = 28;
+const SERIAL_OFFSET_OFFSET: usize = 36;
+
+/// A UE4 package split into a uasset holding the header and a uexp holding the export data,
+/// like the ones scripts are stored in by Guilty Gear Strive
+#[derive(Debug, Clone)]
+pub struct UassetPair {
+    pub uasset: Vec<u8>,
+    pub uexp: Vec<u8>,
+    /// Size of the header, which is where the uexp data starts in offsets from the uasset
+    header_size: usize,
+    /// Offset of the first export map entry in the uasset
+    export_offset: usize,
+    export_count: usize,
+    /// Position of `BulkDataStartOffset` in the summary
+    bulk_data_position: usize,
+}
+
+impl UassetPair {
+    pub fn new(uasset: Vec<u8>, uexp: Vec<u8>) -> Result<Self, BBScriptError> {
+        let mut pair = Self {
+            uasset,
+            uexp,
+            header_size: 0,
+            export_offset: 0,
+            export_count: 0,
+            bulk_data_position: 0,
+        };
+
+        pair.read_summary()?;
+
+        let export_map_end = pair
+            .export_count
+            .checked_mul(EXPORT_ENTRY_SIZE)
+            .and_then(|size| size.checked_add(pair.export_offset));
+        if export_map_end.is_none_or(|end| end > pair.uasset.len()) {
+            return Err(invalid("export map is outside of the uasset"));
+        }
+
+        Ok(pair)
+    }
+
+    /// Reads the summary fields up to the bulk data offset
+    fn read_summary(&mut self) -> Result<(), BBScriptError> {
+        let mut summary = Cursor::new(self.uasset.as_slice());
+
+        if read_u32(&mut summary)? != UE4_PACKAGE_TAG {
+            return Err(invalid("missing package tag"));
+        }
+
+        let legacy_version = read_i32(&mut summary)?;
+        if !(-7..=-3).contains(&legacy_version) {
+            return Err(invalid(&format!(
+                "unsupported legacy file version {legacy_version}"
+            )));
+        }
+        if legacy_version != -4 {
+            // UE3 version
+            read_i32(&mut summary)?;
+        }
+
+        let file_version = read_i32(&mut summary)?;
+        if file_version < VER_UE4_64BIT_EXPORTMAP_SERIALSIZES {
+            return Err(invalid(&format!("unsupported file version {file_version}")));
+        }
+
+        // licensee version
+        read_i32(&mut summary)?;
+
+        // custom versions are a GUID and a version number each
+        let custom_versions = read_i32(&mut summary)?;
+        skip(&mut summary, i64::from(custom_versions) * 20)?;
+
+        self.header_size = read_size(&mut summary, "header size")?;
+
+        skip_string(&mut summary)?;
+        let package_flags = read_u32(&mut summary)?;
+
+        // name count and offset
+        read_i32(&mut summary)?;
+        read_i32(&mut summary)?;
+
+        if package_flags & PKG_FILTER_EDITOR_ONLY == 0
+            && file_version >= VER_UE4_ADDED_PACKAGE_SUMMARY_LOCALIZATION_ID
+        {
+            skip_string(&mut summary)?;
+        }
+
+        if file_version >= VER_UE4_SERIALIZE_TEXT_IN_PACKAGES {
+            // gatherable text data count and offset
+            read_i32(&mut summary)?;
+            read_i32(&mut summary)?;
+        }
+
+        self.export_count = read_size(&mut summary, "export count")?;
+        self.export_offset = read_size(&mut summary, "export offset")?;
+
+        // import count and offset, depends offset, soft package reference count and offset,
+        // searchable names offset and thumbnail table offset
+        skip(&mut summary, 7 * 4)?;
+        // package GUID
+        skip(&mut summary, 16)?;
+        if file_version >= VER_UE4_ADDED_PACKAGE_OWNER
+            && package_flags & PKG_FILTER_EDITOR_ONLY == 0
+        {
+            // persistent GUID, and owner persistent GUID for older versions
+            skip(&mut summary, 16)?;
+            if file_version < VER_UE4_NON_OUTER_PACKAGE_IMPORT {
+                skip(&mut summary, 16)?;
+            }
+        }
+
+        // generations are an export and name count each
+        let generations = read_i32(&mut summary)?;
+        skip(&mut summary, i64::from(generations) * 8)?;
+
+        // saved by and compatible with engine versions
+        for _ in 0..2 {
+            skip(&mut summary, 3 * 2 + 4)?;
+            skip_string(&mut summary)?;
+        }
+
+        // compression flags
+        read_u32(&mut summary)?;
+        let compressed_chunks = read_i32(&mut summary)?;
+        skip(&mut summary, i64::from(compressed_chunks) * 16)?;
+        // package source
+        read_u32(&mut summary)?;
+
+        let additional_packages = read_i32(&mut summary)?;
+        for _ in 0..additional_packages {
+            skip_string(&mut summary)?;
+        }
+        if legacy_version > -7 {
+            // texture allocations
+            read_i32(&mut summary)?;
+        }
+
+        // asset registry data offset
+        read_i32(&mut summary)?;
+
+        self.bulk_data_position = summary.position() as usize;
+        summary
+            .read_i64::<LittleEndian>()
+            .map_err(|_| invalid("package summary ends early"))?;
+
+        Ok(())
+    }
+
+    /// Finds the script inside of the uexp, along with the data around it
+    pub fn script(&self) -> Result<(&[u8], Container), BBScriptError> {
+        let container = Container::detect(&self.uexp)
+            .filter(|c| c.kind == ContainerKind::Ue4Export)
+            .ok_or_else(|| invalid("no script found in the uexp"))?;
+
+        Ok((
+            &self.uexp[container.script_range(self.uexp.len())],
+            container,
+        ))
+    }
+
+    /// Replaces the script, updating the export holding it and the offsets of everything after it
+    pub fn replace_script(&self, script: &[u8]) -> Result<Self, BBScriptError> {
+        let (old_script, container) = self.script()?;
+        let delta = script.len() as i64 - old_script.len() as i64;
+
+        // offsets in the export map count from the start of the uasset
+        let script_offset = (self.header_size + container.prefix.len()) as i64;
+
+        let mut uasset = self.uasset.clone();
+        let mut found = false;
+        for index in 0..self.export_count {
+            let entry = self.export_offset + index * EXPORT_ENTRY_SIZE;
+            let size_field = entry + SERIAL_SIZE_OFFSET..entry + SERIAL_SIZE_OFFSET + 8;
+            let offset_field = entry + SERIAL_OFFSET_OFFSET..entry + SERIAL_OFFSET_OFFSET + 8;
+
+            let size = LittleEndian::read_i64(&uasset[size_field.clone()]);
+            let offset = LittleEndian::read_i64(&uasset[offset_field.clone()]);
+            let end = offset.checked_add(size).ok_or_else(overflow)?;
+
+            if (offset..end).contains(&script_offset) {
+                let size = size.checked_add(delta).ok_or_else(overflow)?;
+                LittleEndian::write_i64(&mut uasset[size_field], size);
+                found = true;
+            } else if offset > script_offset {
+                let offset = offset.checked_add(delta).ok_or_else(overflow)?;
+                LittleEndian::write_i64(&mut uasset[offset_field], offset);
+            }
+        }
+
+        if !found {
+            return Err(invalid("no export contains the script"));
+        }
+
+        // the bulk data offset points at the package tag closing the uexp
+        let bulk_data_field = self.bulk_data_position..self.bulk_data_position + 8;
+        let bulk_data_offset = LittleEndian::read_i64(&uasset[bulk_data_field.clone()]);
+        if bulk_data_offset > script_offset {
+            let bulk_data_offset = bulk_data_offset.checked_add(delta).ok_or_else(overflow)?;
+            LittleEndian::write_i64(&mut uasset[bulk_data_field], bulk_data_offset);
+        }
+
+        Ok(Self {
+            uasset,
+            uexp: container.wrap(script),
+            ..*self
+        })
+    }
+}
+
+fn read_i32(summary: &mut Cursor<&[u8]>) -> Result<i32, BBScriptError> {
+    summary
+        .read_i32::<LittleEndian>()
+        .map_err(|_| invalid("package summary ends early"))
+}
+
+/// Reads an `i32` count or offset, which can't be negative
+fn read_size(summary: &mut Cursor<&[u8]>, name: &str) -> Result<usize, BBScriptError> {
+    usize::try_from(read_i32(summary)?).map_err(|_| invalid(&format!("negative {name}")))
+}
+
+fn read_u32(summary: &mut Cursor<&[u8]>) -> Result<u32, BBScriptError> {
+    summary
+        .read_u32::<LittleEndian>()
+        .map_err(|_| invalid("package summary ends early"))
+}
+
+fn skip(summary: &mut Cursor<&[u8]>, size: i64) -> Result<(), BBScriptError> {
+    summary.seek(SeekFrom::Current(size))?;
+    Ok(())
+}
+
+fn skip_string(summary: &mut Cursor<&[u8]>) -> Result<(), BBScriptError> {
+    let len = i64::from(read_i32(summary)?);
+
+    // negative lengths are UTF-16 strings
+    skip(summary, if len < 0 { -len * 2 } else { len })
+}
+
+fn invalid(reason: &str) -> BBScriptError {
+    BBScriptError::InvalidUasset(reason.into())
+}
+
+fn overflow() -> BBScriptError {
+    invalid("export sizes or offsets are out of range")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::container::UE4_PACKAGE_TAG;
+    use crate::fixture::{bytes, STATES};
+    use crate::uasset::{UassetPair, EXPORT_ENTRY_SIZE, SERIAL_OFFSET_OFFSET, SERIAL_SIZE_OFFSET};
+    use byteorder::{ByteOrder, LittleEndian};
+
+    /// A cooked 4.25 package with the script export followed by a second export
+    fn package(script: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut uexp = Vec::new();
+        uexp.extend_from_slice(&[0x11; 0x19]);
+        uexp.extend_from_slice(&(script.len() as u32 + 4).to_le_bytes());
+        uexp.extend_from_slice(&[0; 13]);
+        uexp.extend_from_slice(&(script.len() as u32).to_le_bytes());
+        uexp.extend_from_slice(script);
+        let first_export_size = uexp.len();
+        uexp.extend_from_slice(&[0x22; 0x10]);
+        uexp.extend_from_slice(&UE4_PACKAGE_TAG.to_le_bytes());
+
+        let mut summary = Vec::new();
+        summary.extend_from_slice(&UE4_PACKAGE_TAG.to_le_bytes());
+        for value in [-7i32, 864, 518, 0, 0] {
+            summary.extend_from_slice(&value.to_le_bytes());
+        }
+        let header_size_position = summary.len();
+        summary.extend_from_slice(&[0; 4]);
+        // folder name "None"
+        summary.extend_from_slice(&5i32.to_le_bytes());
+        summary.extend_from_slice(b"None\0");
+        summary.extend_from_slice(&0x80000000u32.to_le_bytes());
+        // names, gatherable text, then the export count and offset
+        for value in [0i32, 0, 0, 0, 2] {
+            summary.extend_from_slice(&value.to_le_bytes());
+        }
+        let export_offset_position = summary.len();
+        summary.extend_from_slice(&[0; 4]);
+        // imports, depends, soft package references, searchable names, thumbnails and the package GUID
+        summary.extend_from_slice(&[0; 7 * 4 + 16]);
+        // no generations, then the saved by and compatible engine versions with empty branch names
+        summary.extend_from_slice(&[0; 4 + 2 * (3 * 2 + 4 + 4)]);
+        // compression flags, compressed chunks, package source, additional packages and asset registry offset
+        summary.extend_from_slice(&[0; 5 * 4]);
+        let bulk_data_position = summary.len();
+        summary.extend_from_slice(&[0; 8]);
+
+        let export_offset = summary.len();
+        let header_size = export_offset + 2 * EXPORT_ENTRY_SIZE;
+        let mut uasset = summary;
+        uasset.resize(header_size, 0);
+
+        LittleEndian::write_i32(&mut uasset[header_size_position..], header_size as i32);
+        LittleEndian::write_i32(&mut uasset[export_offset_position..], export_offset as i32);
+        LittleEndian::write_i64(
+            &mut uasset[bulk_data_position..],
+            (header_size + uexp.len() - 4) as i64,
+        );
+
+        let exports = [
+            (header_size, first_export_size),
+            (header_size + first_export_size, 0x10),
+        ];
+        for (index, (offset, size)) in exports.into_iter().enumerate() {
+            let entry = export_offset + index * EXPORT_ENTRY_SIZE;
+            LittleEndian::write_i64(&mut uasset[entry + SERIAL_SIZE_OFFSET..], size as i64);
+            LittleEndian::write_i64(&mut uasset[entry + SERIAL_OFFSET_OFFSET..], offset as i64);
+        }
+
+        (uasset, uexp)
+    }
+
+    #[test]
+    fn repack_updates_exports() {
+        let small = bytes("beginState: s32'CmnActStand'\nendState:\n");
+        let big = bytes(STATES);
+
+        let (uasset, uexp) = package(&small);
+
+        // negative sizes are rejected instead of wrapping around
+        let mut negative = uasset.clone();
+        LittleEndian::write_i32(&mut negative[24..], -1);
+        assert!(UassetPair::new(negative, uexp.clone()).is_err());
+
+        let pair = UassetPair::new(uasset, uexp).unwrap();
+        assert_eq!(pair.script().unwrap().0, small.as_slice());
+
+        let repacked = pair.replace_script(&big).unwrap();
+        let (expected_uasset, expected_uexp) = package(&big);
+
+        assert_eq!(repacked.script().unwrap().0, big.as_slice());
+        assert_eq!(repacked.uexp, expected_uexp);
+        assert_eq!(repacked.uasset, expected_uasset);
+    }
+}
@@ -0,0 +1,136 @@
+use byteorder::ByteOrder;
+use serde::Serialize;
+
+use crate::ast::Script;
+use crate::error::BBScriptError;
+use crate::game_config::ScriptConfig;
+use crate::parser::instruction_name;
+use crate::rebuilder::rebuild_readable;
+
+/// Indent limit used for the readable script, which doesn't change the rebuilt output
+const VERIFY_INDENT_LIMIT: usize = 12;
+
+/// Where a script stopped matching itself after being parsed and rebuilt
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    /// Offset of the first differing byte from the start of the script, including the jump table
+    pub offset: usize,
+    pub original_len: usize,
+    pub rebuilt_len: usize,
+    /// Whether the difference is in the jump table, before the first state
+    pub in_jump_table: bool,
+    /// Name of the state or subroutine containing the difference, `None` if it isn't in one
+    pub state: Option<String>,
+    /// Name of the instruction containing the difference and its offset from the start of the script
+    pub instruction: Option<(String, usize)>,
+}
+
+impl ScriptConfig {
+    /// Parses a script to its readable form and rebuilds it, returning where the result first differs from the input.
+    /// Returns `None` if the script survives the round trip unchanged
+    pub fn verify_round_trip<B: ByteOrder>(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Option<Mismatch>, BBScriptError> {
+        let input = input.as_ref();
+
+        let readable = self.parse_to_string::<B>(input, VERIFY_INDENT_LIMIT)?;
+        let rebuilt = rebuild_readable::<B>(self, &readable)?;
+
+        let first_difference = input
+            .iter()
+            .zip(&rebuilt)
+            .position(|(a, b)| a != b)
+            .or_else(|| (input.len() != rebuilt.len()).then(|| input.len().min(rebuilt.len())));
+
+        let Some(offset) = first_difference else {
+            return Ok(None);
+        };
+
+        let mut mismatch = Mismatch {
+            offset,
+            original_len: input.len(),
+            rebuilt_len: rebuilt.len(),
+            in_jump_table: false,
+            state: None,
+            instruction: None,
+        };
+
+        let (_, base) = self.read_jump_table::<B>(input)?;
+        let Some(script_offset) = offset.checked_sub(base) else {
+            mismatch.in_jump_table = true;
+            return Ok(Some(mismatch));
+        };
+
+        let script = Script::new(self, self.parse_located::<B>(input)?)?;
+
+        let root = script
+            .roots()
+            .iter()
+            .copied()
+            .find(|id| script.span(*id).contains(&script_offset));
+
+        if let Some(root) = root {
+            let node = script.node(root);
+            mismatch.state = node.name().map(String::from);
+
+            mismatch.instruction = script
+                .node_instructions(root)
+                .into_iter()
+                .find(|i| (i.offset..i.offset + i.size).contains(&script_offset))
+                .map(|i| {
+                    (
+                        instruction_name(self, &i.instruction.identifier),
+                        base + i.offset,
+                    )
+                });
+        }
+
+        Ok(Some(mismatch))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{bytes, STATES};
+    use crate::SupportedGame;
+    use byteorder::LittleEndian;
+
+    #[test]
+    fn verify_reports_first_difference() {
+        let config = SupportedGame::Ggst.into_config();
+        let mut bytes = bytes(STATES);
+
+        assert!(config
+            .verify_round_trip::<LittleEndian>(&bytes)
+            .unwrap()
+            .is_none());
+
+        // data after the terminator of a string doesn't survive the readable format
+        let table_size = 4 + 2 * 0x24;
+        let crouch_sprite = table_size + 0x24 + 0x28 + 0x4 + 0x24;
+        bytes[crouch_sprite + 0x4 + 0x1E] = 0xFF;
+
+        let mismatch = config
+            .verify_round_trip::<LittleEndian>(&bytes)
+            .unwrap()
+            .unwrap();
+
+        assert!((crouch_sprite..crouch_sprite + 0x28).contains(&mismatch.offset));
+        assert!(!mismatch.in_jump_table);
+        assert_eq!(mismatch.state.as_deref(), Some("CmnActCrouch"));
+        assert_eq!(
+            mismatch.instruction,
+            Some(("sprite".to_string(), crouch_sprite))
+        );
+
+        bytes[4 + 0x1F] = 0xFF;
+        let mismatch = config
+            .verify_round_trip::<LittleEndian>(&bytes)
+            .unwrap()
+            .unwrap();
+        assert!(mismatch.offset < table_size);
+        assert!(mismatch.in_jump_table);
+        assert_eq!(mismatch.state, None);
+    }
+}